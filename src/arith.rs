@@ -0,0 +1,286 @@
+// Shared between several day binaries, each of which uses only a part of it.
+#![allow(dead_code)]
+
+use std::cmp::Ordering;
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Op {
+    pub fn symbol(self) -> char {
+        match self {
+            Op::Add => '+',
+            Op::Sub => '-',
+            Op::Mul => '*',
+            Op::Div => '/',
+            Op::Rem => '%',
+        }
+    }
+}
+
+pub struct ArithError {
+    pub backend: &'static str,
+    pub op: Op,
+    pub left: String,
+    pub right: String,
+}
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if matches!(self.op, Op::Div | Op::Rem) && self.right == "0" {
+            write!(f, "{} {} {} divides by zero", self.left, self.op.symbol(), self.right)
+        } else {
+            write!(f, "{} {} {} overflows {}",
+                   self.left, self.op.symbol(), self.right, self.backend)
+        }
+    }
+}
+
+pub trait Integer: Sized + Clone + fmt::Display {
+    const NAME: &'static str;
+
+    fn from_i64(value: i64) -> Self;
+    fn checked(op: Op, left: &Self, right: &Self) -> Option<Self>;
+    fn is_zero(&self) -> bool;
+}
+
+pub fn apply<T: Integer>(op: Op, left: &T, right: &T) -> Result<T, ArithError> {
+    T::checked(op, left, right).ok_or_else(|| ArithError {
+        backend: T::NAME,
+        op,
+        left: left.to_string(),
+        right: right.to_string(),
+    })
+}
+
+macro_rules! impl_integer {
+    ($type:ty, $name:expr) => {
+        impl Integer for $type {
+            const NAME: &'static str = $name;
+
+            fn from_i64(value: i64) -> Self {
+                value as $type
+            }
+
+            fn checked(op: Op, left: &Self, right: &Self) -> Option<Self> {
+                match op {
+                    Op::Add => left.checked_add(*right),
+                    Op::Sub => left.checked_sub(*right),
+                    Op::Mul => left.checked_mul(*right),
+                    Op::Div => left.checked_div(*right),
+                    Op::Rem => left.checked_rem(*right),
+                }
+            }
+
+            fn is_zero(&self) -> bool {
+                *self == 0
+            }
+        }
+    };
+}
+
+impl_integer!(i64, "i64");
+impl_integer!(i128, "i128");
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    I64,
+    I128,
+    Big,
+}
+
+impl Backend {
+    pub fn parse(input: &str) -> Option<Self> {
+        match input {
+            "i64" => Some(Backend::I64),
+            "i128" => Some(Backend::I128),
+            "big" => Some(Backend::Big),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    // little-endian limbs without trailing zeros, zero is an empty vector
+    mag: Vec<u32>,
+}
+
+fn trim(mut mag: Vec<u32>) -> Vec<u32> {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+
+    mag
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0_u64;
+
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+
+    result.push(carry as u32);
+
+    trim(result)
+}
+
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0_i64;
+
+    for (i, &x) in a.iter().enumerate() {
+        let mut diff = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += 1 << 32;
+            borrow = 1;
+        }
+        result.push(diff as u32);
+    }
+
+    trim(result)
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0_u32; a.len() + b.len()];
+
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0_u64;
+        for (j, &y) in b.iter().enumerate() {
+            let cur = result[i + j] as u64 + x as u64 * y as u64 + carry;
+            result[i + j] = cur as u32;
+            carry = cur >> 32;
+        }
+        result[i + b.len()] = carry as u32;
+    }
+
+    trim(result)
+}
+
+fn divrem_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0_u32; a.len()];
+    let mut rem = 0_u64;
+
+    for i in (0..a.len()).rev() {
+        let cur = (rem << 32) | a[i] as u64;
+        quotient[i] = (cur / divisor as u64) as u32;
+        rem = cur % divisor as u64;
+    }
+
+    (trim(quotient), rem as u32)
+}
+
+fn divrem_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let (quotient, rem) = divrem_small(a, b[0]);
+        return (quotient, trim(vec![rem]));
+    }
+
+    let mut quotient = vec![0_u32; a.len()];
+    let mut rem: Vec<u32> = Vec::new();
+
+    for bit in (0..a.len() * 32).rev() {
+        rem = add_mag(&rem, &rem);
+        if a[bit / 32] >> (bit % 32) & 1 == 1 {
+            rem = add_mag(&rem, &[1]);
+        }
+
+        if cmp_mag(&rem, b) != Ordering::Less {
+            rem = sub_mag(&rem, b);
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+
+    (trim(quotient), rem)
+}
+
+impl BigInt {
+    fn new(negative: bool, mag: Vec<u32>) -> Self {
+        let mag = trim(mag);
+        BigInt { negative: negative && !mag.is_empty(), mag }
+    }
+
+    fn add_signed(&self, other: &Self, other_negative: bool) -> Self {
+        if self.negative == other_negative {
+            return BigInt::new(self.negative, add_mag(&self.mag, &other.mag));
+        }
+
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::new(other_negative, sub_mag(&other.mag, &self.mag)),
+            _ => BigInt::new(self.negative, sub_mag(&self.mag, &other.mag)),
+        }
+    }
+}
+
+impl Integer for BigInt {
+    const NAME: &'static str = "big";
+
+    fn from_i64(value: i64) -> Self {
+        let abs = value.unsigned_abs();
+        BigInt::new(value < 0, vec![abs as u32, (abs >> 32) as u32])
+    }
+
+    fn checked(op: Op, left: &Self, right: &Self) -> Option<Self> {
+        let negative = left.negative != right.negative;
+
+        Some(match op {
+            Op::Add => left.add_signed(right, right.negative),
+            Op::Sub => left.add_signed(right, !right.negative),
+            Op::Mul => BigInt::new(negative, mul_mag(&left.mag, &right.mag)),
+            Op::Div | Op::Rem if right.is_zero() => return None,
+            Op::Div => BigInt::new(negative, divrem_mag(&left.mag, &right.mag).0),
+            Op::Rem => BigInt::new(left.negative, divrem_mag(&left.mag, &right.mag).1),
+        })
+    }
+
+    fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+
+        const CHUNK: u32 = 1_000_000_000;
+
+        let mut chunks = Vec::new();
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            let (quotient, rem) = divrem_small(&mag, CHUNK);
+            chunks.push(rem);
+            mag = quotient;
+        }
+
+        if self.negative {
+            write!(f, "-")?;
+        }
+
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,10 +1,17 @@
+mod arith;
+
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::cmp::Reverse;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::path::Path;
+use std::process;
+
+use arith::{ArithError, Backend, BigInt, Integer};
 
-fn parse_monkey_start_items(line: String) -> Vec<u64> {
+fn parse_monkey_start_items(line: String) -> Vec<i64> {
     let mut split = line.split_ascii_whitespace();
     let _ = split.nth(1);
 
@@ -12,7 +19,7 @@ fn parse_monkey_start_items(line: String) -> Vec<u64> {
 
     for item in split {
         let item_idx = item.trim_end_matches(',')
-                           .parse::<u64>().unwrap();
+                           .parse::<i64>().unwrap();
         start_items.push(item_idx);
     }
 
@@ -22,7 +29,7 @@ fn parse_monkey_start_items(line: String) -> Vec<u64> {
 #[derive(Clone)]
 enum Operand {
     Input,
-    Const(u64),
+    Const(i64),
 }
 
 #[derive(Clone)]
@@ -32,23 +39,25 @@ enum Operation {
 }
 
 impl Operation {
-    pub fn evaluate(&self, input: u64) -> u64 {
+    pub fn evaluate<T: Integer>(&self, input: &T) -> Result<T, ArithError> {
         use self::Operation::*;
 
         match self {
-            Add(l, r) => Self::eval_operand(l, input)
-                         + Self::eval_operand(r, input),
-            Multiply(l, r) => Self::eval_operand(l, input)
-                              * Self::eval_operand(r, input),
+            Add(l, r) => arith::apply(arith::Op::Add,
+                                      &Self::eval_operand(l, input),
+                                      &Self::eval_operand(r, input)),
+            Multiply(l, r) => arith::apply(arith::Op::Mul,
+                                           &Self::eval_operand(l, input),
+                                           &Self::eval_operand(r, input)),
         }
     }
 
-    fn eval_operand(operand: &Operand, input: u64) -> u64 {
+    fn eval_operand<T: Integer>(operand: &Operand, input: &T) -> T {
         use self::Operand::*;
 
         match operand {
-            Input => input,
-            Const(val) => *val,
+            Input => input.clone(),
+            Const(val) => T::from_i64(*val),
         }
     }
 }
@@ -58,7 +67,7 @@ fn parse_operand(string: &str) -> Operand {
 
     match string {
         "old" => Input,
-        x => Const(x.parse::<u64>().unwrap()),
+        x => Const(x.parse::<i64>().unwrap()),
     }
 }
 
@@ -83,17 +92,19 @@ fn parse_monkey_operation(line: String) -> Operation {
 
 #[derive(Clone)]
 struct PassTo {
-    divisible_by: u64,
+    divisible_by: i64,
     if_true: usize,
     if_false: usize,
 }
 
 impl PassTo {
-    fn evaluate(&self, input: u64) -> usize {
-        if input % self.divisible_by == 0 {
-            self.if_true
+    fn evaluate<T: Integer>(&self, input: &T) -> Result<usize, ArithError> {
+        let rem = arith::apply(arith::Op::Rem, input, &T::from_i64(self.divisible_by))?;
+
+        if rem.is_zero() {
+            Ok(self.if_true)
         } else {
-            self.if_false
+            Ok(self.if_false)
         }
     }
 }
@@ -103,7 +114,7 @@ where I: Iterator<Item = std::io::Result<String>> {
     let test_ln = lines.next().unwrap().unwrap();
     let divisible_by = test_ln.split_ascii_whitespace()
                               .last().unwrap()
-                              .parse::<u64>().unwrap();
+                              .parse::<i64>().unwrap();
 
     let if_true_ln = lines.next().unwrap().unwrap();
     let if_true = if_true_ln.split_ascii_whitespace()
@@ -120,7 +131,7 @@ where I: Iterator<Item = std::io::Result<String>> {
 
 #[derive(Clone)]
 struct Monkey {
-    items: Vec<u64>,
+    items: Vec<i64>,
     operation: Operation,
     throws_to: PassTo,
 }
@@ -149,28 +160,87 @@ where T: Default + PartialOrd
     a * b / gcd(a, b)
 }
 
-fn play_keep_away<F>(mut monkeys: Vec<Monkey>, num_rounds: u16,
-                     manage_worry: F) -> u64
-where F: FnOnce(u64) -> u64 + Copy {
+struct KeepAwayError {
+    monkey: usize,
+    round: u16,
+    error: ArithError,
+}
+
+impl fmt::Display for KeepAwayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "monkey {} in round {} computes {}", self.monkey, self.round, self.error)
+    }
+}
+
+fn play_keep_away<T, F>(monkeys: &[Monkey], num_rounds: u16,
+                        manage_worry: F) -> Result<u64, KeepAwayError>
+where T: Integer,
+      F: Fn(&T) -> Result<T, ArithError> {
+    let mut items: Vec<Vec<T>> = monkeys
+        .iter()
+        .map(|m| m.items.iter().map(|&i| T::from_i64(i)).collect())
+        .collect();
     let mut items_inspected = vec![0; monkeys.len()];
 
-    for _ in 0..num_rounds {
-        for i in 0..monkeys.len() {
-            let monkey_items: Vec<u64> = monkeys[i].items.drain(..).collect();
+    for round in 1..=num_rounds {
+        for (i, monkey) in monkeys.iter().enumerate() {
+            let monkey_items: Vec<T> = items[i].drain(..).collect();
             for item in monkey_items {
-                let worry = manage_worry(monkeys[i].operation.evaluate(item));
-                let pass_to = monkeys[i].throws_to.evaluate(worry);
+                let pass = |item: &T| -> Result<(T, usize), ArithError> {
+                    let worry = manage_worry(&monkey.operation.evaluate(item)?)?;
+                    let pass_to = monkey.throws_to.evaluate(&worry)?;
+
+                    Ok((worry, pass_to))
+                };
+
+                let (worry, pass_to) = pass(&item).map_err(|error| KeepAwayError {
+                    monkey: i,
+                    round,
+                    error,
+                })?;
 
                 items_inspected[i] += 1;
 
-                monkeys[pass_to].items.push(worry);
+                items[pass_to].push(worry);
             }
         }
     }
 
     items_inspected.sort_unstable_by_key(|x| Reverse(*x));
 
-    items_inspected[..2].into_iter().product()
+    Ok(items_inspected[..2].iter().product())
+}
+
+fn report(part: u8, num_rounds: u16, result: Result<u64, KeepAwayError>) {
+    match result {
+        Ok(monkey_business) => println!("[Part {}] The level of monkey business after {} \
+                                         rounds of stuff-slinging simian shenanigans is {}",
+                                        part, num_rounds, monkey_business),
+        Err(e) => println!("[Part {}] Keep away could not be played for {} rounds, {}",
+                           part, num_rounds, e),
+    }
+}
+
+fn run<T: Integer>(monkeys: &[Monkey], reduce_worry: bool) {
+    const NUM_ROUNDS_PT1: u16 = 20;
+    let three = T::from_i64(3);
+    let monkey_business = play_keep_away(monkeys, NUM_ROUNDS_PT1,
+                                         |x: &T| arith::apply(arith::Op::Div, x, &three));
+    report(1, NUM_ROUNDS_PT1, monkey_business);
+
+    let pass_lcm = T::from_i64(monkeys.iter().fold(
+        1, |acc, monkey| lcm(acc, monkey.throws_to.divisible_by)
+    ));
+
+    const NUM_ROUNDS_PT2: u16 = 10_000;
+    let monkey_business = play_keep_away(monkeys, NUM_ROUNDS_PT2, |x: &T| {
+        if reduce_worry {
+            arith::apply(arith::Op::Rem, x, &pass_lcm)
+        } else {
+            Ok(x.clone())
+        }
+    });
+    report(2, NUM_ROUNDS_PT2, monkey_business);
 }
 
 fn main() {
@@ -184,7 +254,7 @@ fn main() {
 
     let mut monkeys = Vec::new();
 
-    while let Some(_) = lines.peek() {
+    while lines.peek().is_some() {
         let line = lines.nth(1).unwrap().unwrap();
         let items = parse_monkey_start_items(line);
 
@@ -198,21 +268,23 @@ fn main() {
         lines.next();
     }
 
-    const NUM_ROUNDS_PT1: u16 = 20;
-    let monkey_business = play_keep_away(monkeys.clone(), NUM_ROUNDS_PT1,
-                                         |x| x / 3);
-    println!("[Part 1] The level of monkey business after {} rounds of \
-              stuff-slinging simian shenanigans is {}",
-             NUM_ROUNDS_PT1, monkey_business);
-
-    let pass_lcm = monkeys.iter().fold(
-        1, |acc, monkey| lcm(acc, monkey.throws_to.divisible_by)
-    );
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let backend = match args.iter().position(|a| a == "--arith") {
+        None => Backend::I64,
+        Some(i) => {
+            let name = args.get(i + 1).map_or("", String::as_str);
+            Backend::parse(name).unwrap_or_else(|| {
+                eprintln!("unknown arithmetic '{}', expected one of: i64, i128, big", name);
+                process::exit(1);
+            })
+        },
+    };
+    let reduce_worry = !args.iter().any(|a| a == "--unreduced");
 
-    const NUM_ROUNDS_PT2: u16 = 10_000;
-    let monkey_business = play_keep_away(monkeys.clone(), NUM_ROUNDS_PT2,
-                                         |x| x % pass_lcm);
-    println!("[Part 2] The level of monkey business after {} rounds of \
-              stuff-slinging simian shenanigans is {}",
-             NUM_ROUNDS_PT2, monkey_business);
+    match backend {
+        Backend::I64 => run::<i64>(&monkeys, reduce_worry),
+        Backend::I128 => run::<i128>(&monkeys, reduce_worry),
+        Backend::Big => run::<BigInt>(&monkeys, reduce_worry),
+    }
 }
@@ -1,27 +1,34 @@
 use core::cmp::{min, max};
-use std::fs::File;
+use std::env;
+use std::fs::{self, File};
 use std::io::prelude::*;
 use std::io::BufReader;
-use std::iter;
 use std::path::Path;
+use std::process;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Position {
-    x: usize,
-    y: usize,
+    x: isize,
+    y: isize,
 }
 
-const SPAWN_X: usize = 500;
-const SPAWN_Y: usize = 0;
+const SOURCE: Position = Position { x: 500, y: 0 };
 
-type MinMax = (usize, usize);
+type MinMax = (isize, isize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
+    Air,
+    Rock,
+    Sand,
+}
 
 fn init_map(
     (min_x, max_x): MinMax,
     (min_y, max_y): MinMax,
     paths: &Vec<Vec<Position>>
-) -> Vec<Vec<bool>> {
-    let mut map = vec![vec![false; max_x - min_x + 1]; max_y - min_y + 1];
+) -> Vec<Vec<Tile>> {
+    let mut map = vec![vec![Tile::Air; (max_x - min_x + 1) as usize]; (max_y - min_y + 1) as usize];
 
     for path in paths {
         for win in path.windows(2) {
@@ -37,14 +44,14 @@ fn init_map(
             if from.x == to.x {
                 let start = min(from.y, to.y);
                 let end = max(from.y, to.y);
-                for y in start..=end {
-                    map[y][to.x] = true;
+                for row in &mut map[start as usize..=end as usize] {
+                    row[to.x as usize] = Tile::Rock;
                 }
             } else if from.y == to.y {
                 let start = min(from.x, to.x);
                 let end = max(from.x, to.x);
-                for x in start..=end {
-                    map[to.y][x] = true;
+                for tile in &mut map[to.y as usize][start as usize..=end as usize] {
+                    *tile = Tile::Rock;
                 }
             }
         }
@@ -53,103 +60,272 @@ fn init_map(
     map
 }
 
-fn part1(
-    (min_x, max_x): MinMax,
-    (min_y, max_y): MinMax,
-    paths: Vec<Vec<Position>>
-) {
-    let mut map = init_map((min_x, max_x), (min_y, max_y), &paths);
+// a cave whose grid widens on demand, so a floor does not need to be
+// sized up front to fit the whole pile of sand
+struct Cave {
+    // position of the top left tile of the grid
+    left: isize,
+    top: isize,
+    rows: Vec<Vec<Tile>>,
+    // y of an endless rock floor, without one grains fall past the lowest rock
+    floor: Option<isize>,
+    bottom: isize,
+    // tiles the last grain passed through, kept to draw the one lost in the abyss
+    trail: Vec<Position>,
+}
 
-    let spawn_pos = Position {
-        x: SPAWN_X - min_x,
-        y: SPAWN_Y - min_y,
-    };
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fall {
+    Settled(Position),
+    Abyss,
+    Blocked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Abyss,
+    SourceBlocked,
+    GrainLimit,
+}
 
-    let mut count: u32 = 0;
+struct SandParams {
+    spawns: Vec<Position>,
+    floor: Option<isize>,
+    max_grains: Option<usize>,
+}
 
-    'spawn: loop {
-        let mut next = spawn_pos;
+impl Cave {
+    fn new(paths: &Vec<Vec<Position>>, spawns: &[Position], floor: Option<isize>) -> Self {
+        let ((min_x, max_x), (min_y, max_y)) = paths
+            .iter()
+            .flatten()
+            .chain(spawns)
+            .fold(((isize::MAX, isize::MIN), (isize::MAX, isize::MIN)), |acc, p| {
+                (
+                    (min(acc.0.0, p.x), max(acc.0.1, p.x)),
+                    (min(acc.1.0, p.y), max(acc.1.1, p.y))
+                )
+        });
 
-        loop {
-            if next.y + 1 == map.len() {
-                break 'spawn;
-            } else if map[next.y + 1][next.x] == false {
-                next.y += 1;
-            } else if next.x == 0 {
-                break 'spawn;
-            } else if map[next.y + 1][next.x - 1] == false {
-                next.y += 1;
-                next.x -= 1;
-            } else if next.x + 1 == map[0].len() {
-                break 'spawn;
-            } else if map[next.y + 1][next.x + 1] == false {
-                next.y += 1;
-                next.x += 1;
-            } else {
-                map[next.y][next.x] = true;
-                break;
+        let bottom = paths.iter().flatten().map(|p| p.y).max().unwrap_or(min_y);
+        let max_y = floor.map_or(max_y, |floor| max(max_y, floor - 1));
+
+        Cave {
+            left: min_x,
+            top: min_y,
+            rows: init_map((min_x, max_x), (min_y, max_y), paths),
+            floor,
+            bottom,
+            trail: Vec::new(),
+        }
+    }
+
+    fn width(&self) -> isize {
+        self.rows[0].len() as isize
+    }
+
+    fn tile(&self, p: Position) -> Tile {
+        if Some(p.y) == self.floor {
+            return Tile::Rock;
+        }
+
+        let (x, y) = (p.x - self.left, p.y - self.top);
+        if x < 0 || y < 0 || x >= self.width() || y >= self.rows.len() as isize {
+            return Tile::Air;
+        }
+
+        self.rows[y as usize][x as usize]
+    }
+
+    // grows the grid at least twice as wide when a column falls outside it
+    fn widen(&mut self, x: isize) {
+        let width = self.width();
+        if x < self.left {
+            let extra = max(self.left - x, width) as usize;
+            for row in &mut self.rows {
+                row.splice(0..0, vec![Tile::Air; extra]);
+            }
+            self.left -= extra as isize;
+        } else if x >= self.left + width {
+            let extra = max(x - self.left - width + 1, width) as usize;
+            for row in &mut self.rows {
+                row.resize(row.len() + extra, Tile::Air);
             }
         }
+    }
 
-        count += 1;
+    fn set(&mut self, p: Position, tile: Tile) {
+        self.widen(p.x);
+        self.rows[(p.y - self.top) as usize][(p.x - self.left) as usize] = tile;
     }
 
-    println!("[Part 1] The amount of units of sand that come to rest \
-              before sand starts flowing into the abyss below is {}", count);
-}
+    fn drop_grain(&mut self, spawn: Position) -> Fall {
+        self.trail.clear();
+        if self.tile(spawn) != Tile::Air {
+            return Fall::Blocked;
+        }
 
-fn part2(
-    (min_x, max_x): MinMax,
-    (min_y, max_y): MinMax,
-    paths: Vec<Vec<Position>>
-) {
-    let max_y = max_y + 2;
-    let half_width = max_y - min_y;
-    let max_x = max(max_x, SPAWN_X + half_width);
-    let min_x = min(min_x, SPAWN_X - half_width);
-
-    let mut map = init_map((min_x, max_x), (min_y, max_y), &paths);
-    for x in map.last_mut().unwrap() {
-        *x = true;
+        let mut next = spawn;
+        loop {
+            self.trail.push(next);
+            if self.floor.is_none() && next.y >= self.bottom {
+                return Fall::Abyss;
+            }
+
+            let below = [0, -1, 1]
+                .map(|dx| Position { x: next.x + dx, y: next.y + 1 })
+                .into_iter()
+                .find(|&p| self.tile(p) == Tile::Air);
+
+            match below {
+                Some(p) => next = p,
+                None => {
+                    self.set(next, Tile::Sand);
+                    return Fall::Settled(next);
+                },
+            }
+        }
     }
 
-    let spawn_pos = Position {
-        x: SPAWN_X - min_x,
-        y: SPAWN_Y - min_y,
-    };
+    // the tiles sand ends up covering once every source is blocked, a grain
+    // settles on a tile whenever it can reach it, that is when the tile is
+    // free and sand covers one of the three tiles above it
+    fn fill(&self, spawns: &[Position]) -> usize {
+        let floor = self.floor.expect("filling needs a floor to stop the sand");
+        let top = spawns.iter().map(|p| p.y).min().unwrap();
+        let spread = floor - top;
+        let left = spawns.iter().map(|p| p.x).min().unwrap() - spread;
+        let width = (spawns.iter().map(|p| p.x).max().unwrap() + spread - left + 1) as usize;
+
+        let mut count = 0;
+        let mut row = vec![false; width];
+        for y in top..floor {
+            let above = row;
+            row = (0..width)
+                .map(|i| {
+                    let p = Position { x: left + i as isize, y };
+                    let from_above = above[i.saturating_sub(1)..=min(i + 1, width - 1)]
+                        .iter()
+                        .any(|&sand| sand);
 
-    let mut count: u32 = 0;
+                    (from_above || spawns.contains(&p)) && self.tile(p) == Tile::Air
+                })
+                .collect();
 
-    loop {
-        let mut next = spawn_pos;
-        if map[next.y][next.x] == true {
-            break;
+            count += row.iter().filter(|&&sand| sand).count();
         }
 
+        count
+    }
+
+    // drops grains from the spawn points in turn, skipping the blocked ones,
+    // and returns how many came to rest
+    fn pour(&mut self, params: &SandParams) -> (usize, Stop) {
+        self.pour_with(params, |_, _| ())
+    }
+
+    // same as pour, calling back after every grain that was dropped
+    fn pour_with<F>(&mut self, params: &SandParams, mut on_grain: F) -> (usize, Stop)
+    where F: FnMut(&Cave, Fall) {
+        let mut spawns = params.spawns.clone();
+        let mut count = 0;
+        let mut turn = 0;
+
         loop {
-            if map[next.y + 1][next.x] == false {
-                next.y += 1;
-            } else if next.x == 0 {
-                panic!("next.x - 1 < 0");
-            } else if map[next.y + 1][next.x - 1] == false {
-                next.y += 1;
-                next.x -= 1;
-            } else if next.x + 1 == map[0].len() {
-                panic!("next.x + 1 > map[0].len() - 1");
-            } else if map[next.y + 1][next.x + 1] == false {
-                next.y += 1;
-                next.x += 1;
-            } else {
-                map[next.y][next.x] = true;
-                break;
+            if params.max_grains.is_some_and(|limit| count >= limit) {
+                return (count, Stop::GrainLimit);
+            }
+            if spawns.is_empty() {
+                return (count, Stop::SourceBlocked);
+            }
+
+            turn %= spawns.len();
+            let fall = self.drop_grain(spawns[turn]);
+            match fall {
+                Fall::Settled(_) => {
+                    count += 1;
+                    turn += 1;
+                },
+                Fall::Abyss => {
+                    on_grain(self, fall);
+                    return (count, Stop::Abyss);
+                },
+                Fall::Blocked => {
+                    spawns.remove(turn);
+                    continue;
+                },
             }
+
+            on_grain(self, fall);
+        }
+    }
+
+    // draws the cave the way the puzzle does, with the sources as '+' and
+    // the path of a grain falling into the abyss as '~'
+    fn render(&self, spawns: &[Position], show_trail: bool) -> String {
+        let trail: &[Position] = if show_trail { &self.trail } else { &[] };
+
+        let mut xs: Vec<isize> = spawns.iter().chain(trail).map(|p| p.x).collect();
+        let mut bottom = self.floor.unwrap_or(self.bottom);
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, &tile) in row.iter().enumerate() {
+                if tile != Tile::Air {
+                    xs.push(self.left + x as isize);
+                    bottom = max(bottom, self.top + y as isize);
+                }
+            }
+        }
+        bottom = trail.iter().map(|p| p.y).fold(bottom, max);
+
+        let (from, to) = (xs.iter().min().unwrap() - 1, xs.iter().max().unwrap() + 1);
+        let mut out = String::new();
+        for y in self.top..=bottom {
+            for x in from..=to {
+                let p = Position { x, y };
+                out.push(match self.tile(p) {
+                    Tile::Rock => '#',
+                    Tile::Sand => 'o',
+                    Tile::Air if spawns.contains(&p) => '+',
+                    Tile::Air if trail.contains(&p) => '~',
+                    Tile::Air => '.',
+                });
+            }
+            out.push('\n');
         }
 
-        count += 1;
+        out
     }
+}
+
+fn part1(paths: &Vec<Vec<Position>>) {
+    let params = SandParams { spawns: vec![SOURCE], floor: None, max_grains: None };
+    let (count, _) = Cave::new(paths, &params.spawns, params.floor).pour(&params);
+
+    println!("[Part 1] The amount of units of sand that come to rest \
+              before sand starts flowing into the abyss below is {}", count);
+}
+
+fn part2(paths: &Vec<Vec<Position>>, check: bool) {
+    let max_y = paths.iter().flatten().map(|p| p.y).max().unwrap();
+
+    let params = SandParams { spawns: vec![SOURCE], floor: Some(max_y + 2), max_grains: None };
+    let mut cave = Cave::new(paths, &params.spawns, params.floor);
+    let count = cave.fill(&params.spawns);
 
     println!("[Part 2] The amount of units of sand that come to rest \
               before the source is blocked is {}", count);
+
+    if check {
+        let (simulated, _) = cave.pour(&params);
+        assert_eq!(count, simulated, "the fill disagrees with the simulation");
+        println!("The step by step simulation agrees");
+    }
+}
+
+fn parse_position(s: &str) -> Option<Position> {
+    let (x, y) = s.split_once(',')?;
+
+    Some(Position { x: x.trim().parse().ok()?, y: y.trim().parse().ok()? })
 }
 
 fn main() {
@@ -162,31 +338,92 @@ fn main() {
     let paths = reader
         .lines()
         .map(|l| l.unwrap().split(" -> ")
-            .map(|s| {
-                let (x, y) = s.split_once(',').unwrap();
-
-                Position {
-                    x: x.parse::<usize>().unwrap(),
-                    y: y.parse::<usize>().unwrap()
-                }
-            })
+            .map(|s| parse_position(s).unwrap())
             .collect::<Vec<_>>())
         .collect::<Vec<_>>();
 
-    let ((min_x, max_x), (min_y, max_y)) = paths
+    let args: Vec<String> = env::args().skip(1).collect();
+    let values = |name: &str| args
         .iter()
-        .flatten()
-        .chain(iter::once(
-            &Position {
-                x: SPAWN_X, y: SPAWN_Y
-            }))
-        .fold(((usize::MAX, usize::MIN), (usize::MAX, usize::MIN)), |acc, p| {
-            (
-                (min(acc.0.0, p.x), max(acc.0.1, p.x)),
-                (min(acc.1.0, p.y), max(acc.1.1, p.y))
-            )
-    });
-
-    part1((min_x, max_x), (min_y, max_y), paths.clone());
-    part2((min_x, max_x), (min_y, max_y), paths);
+        .enumerate()
+        .filter(|&(_, a)| a == name)
+        .map(|(i, _)| args.get(i + 1).map(String::as_str).unwrap_or_else(|| {
+            eprintln!("{} expects a value", name);
+            process::exit(1);
+        }))
+        .collect::<Vec<_>>();
+    let number = |name: &str| values(name).last().map(|v| v.parse().unwrap_or_else(|_| {
+        eprintln!("{} expects a number", name);
+        process::exit(1);
+    }));
+
+    let spawns = values("--spawn")
+        .into_iter()
+        .map(|v| parse_position(v).unwrap_or_else(|| {
+            eprintln!("--spawn expects a position like 500,0");
+            process::exit(1);
+        }))
+        .collect::<Vec<_>>();
+    let floor = number("--floor");
+    let max_grains = number("--grains").map(|n| n as usize);
+
+    let render = args.iter().any(|a| a == "--render");
+    let animate = args.iter().any(|a| a == "--animate");
+    let frames = values("--frames").last().map(|dir| Path::new(*dir));
+
+    let custom = !spawns.is_empty() || floor.is_some() || max_grains.is_some();
+    if custom || render || animate || frames.is_some() {
+        let spawns = if spawns.is_empty() { vec![SOURCE] } else { spawns };
+        if let Some(floor) = floor {
+            if let Some(spawn) = spawns.iter().find(|p| p.y >= floor) {
+                eprintln!("sand spawned at {},{} is not above the floor at {}",
+                          spawn.x, spawn.y, floor);
+                process::exit(1);
+            }
+        }
+        let params = SandParams { spawns, floor, max_grains };
+        let mut cave = Cave::new(&paths, &params.spawns, params.floor);
+
+        if let Some(dir) = frames {
+            if let Err(e) = fs::create_dir_all(dir) {
+                eprintln!("could not create {}: {}", dir.display(), e);
+                process::exit(1);
+            }
+        }
+
+        let mut frame = 0;
+        let (count, stop) = cave.pour_with(&params, |cave, fall| {
+            if !animate && frames.is_none() {
+                return;
+            }
+
+            let picture = cave.render(&params.spawns, fall == Fall::Abyss);
+            frame += 1;
+            if animate {
+                println!("After grain {}:\n{}", frame, picture);
+            }
+            if let Some(dir) = frames {
+                let file = dir.join(format!("frame{:06}.txt", frame));
+                if let Err(e) = fs::write(&file, picture) {
+                    eprintln!("could not write {}: {}", file.display(), e);
+                    process::exit(1);
+                }
+            }
+        });
+
+        if render {
+            print!("{}", cave.render(&params.spawns, stop == Stop::Abyss));
+        }
+
+        let reason = match stop {
+            Stop::Abyss => "a grain fell into the abyss",
+            Stop::SourceBlocked => "every source is blocked",
+            Stop::GrainLimit => "the grain limit was reached",
+        };
+        println!("{} units of sand came to rest before {}", count, reason);
+        return;
+    }
+
+    part1(&paths);
+    part2(&paths, args.iter().any(|a| a == "--check"));
 }
@@ -1,7 +1,9 @@
-use std::fs::File;
-use std::io::prelude::*;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
 use std::io::BufReader;
 use std::path::Path;
+use std::process;
 
 #[derive(Debug, Clone, Copy)]
 struct Position {
@@ -45,27 +47,47 @@ fn coalesce_ranges(ranges: Vec<Range>) -> Vec<Range> {
     result
 }
 
-fn get_hor_ranges(y: isize, beacons: &Vec<Data>, bounds: Option<Range>) -> Vec<Range> {
+#[derive(Debug, Clone, Copy)]
+enum Line {
+    Row(isize),
+    Column(isize),
+}
+
+impl Line {
+    // distance of a position to the line and its coordinate along the line
+    fn project(self, pos: Position) -> (usize, isize) {
+        match self {
+            Line::Row(y) => (pos.y.abs_diff(y), pos.x),
+            Line::Column(x) => (pos.x.abs_diff(x), pos.y),
+        }
+    }
+}
+
+fn get_ranges(line: Line, beacons: &Vec<Data>, bounds: Option<Range>) -> Vec<Range> {
     let mut ranges: Vec<Range> = Vec::new();
 
     for beacon in beacons {
         let sensor = beacon.sensor;
         let beacon = beacon.beacon;
         let mht = beacon.manhattan(&sensor);
-        
-        let dist = mht as isize - sensor.y.abs_diff(y) as isize;
-        if dist <= 0 {
+
+        let (offset, center) = line.project(sensor);
+        let dist = mht as isize - offset as isize;
+        if dist < 0 {
             continue;
         }
 
         let mut range = Range {
-            from: sensor.x - dist,
-            to: sensor.x + dist + 1,
+            from: center - dist,
+            to: center + dist + 1,
         };
 
         if let Some(Range{ from, to }) = bounds {
-            range.from = range.from.clamp(from, to);
-            range.to = range.to.clamp(from + 1, to + 1);
+            range.from = range.from.max(from);
+            range.to = range.to.min(to + 1);
+            if range.from >= range.to {
+                continue;
+            }
         }
 
         ranges.push(range);
@@ -82,8 +104,249 @@ fn get_hor_ranges(y: isize, beacons: &Vec<Data>, bounds: Option<Range>) -> Vec<R
     coalesce_ranges(ranges)
 }
 
+// covered positions of a line that are not known beacons
+fn count_no_beacon(line: Line, beacons: &Vec<Data>) -> isize {
+    let ranges = get_ranges(line, beacons, None);
+
+    let mut on_line: Vec<_> = beacons
+        .iter()
+        .map(|d| line.project(d.beacon))
+        .filter(|&(offset, _)| offset == 0)
+        .map(|(_, at)| at)
+        .collect();
+    on_line.sort_unstable();
+    on_line.dedup();
+
+    let covered: isize = ranges.iter().map(|rng| rng.to - rng.from).sum();
+    let known = on_line
+        .into_iter()
+        .filter(|&at| ranges.iter().any(|rng| (rng.from..rng.to).contains(&at)))
+        .count();
+
+    covered - known as isize
+}
+
+// an axis-aligned rectangle in the space rotated by 45 degrees, where
+// u = x + y and v = x - y turn the sensor diamonds into squares
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    u: Range,
+    v: Range,
+}
+
+impl Rect {
+    fn around(pos: Position, radius: isize) -> Self {
+        let (u, v) = (pos.x + pos.y, pos.x - pos.y);
+
+        Rect {
+            u: Range { from: u - radius, to: u + radius + 1 },
+            v: Range { from: v - radius, to: v + radius + 1 },
+        }
+    }
+
+    // the rotated bounding rectangle of the box between two corners
+    fn bounding(lo: Position, hi: Position) -> Self {
+        Rect {
+            u: Range { from: lo.x + lo.y, to: hi.x + hi.y + 1 },
+            v: Range { from: lo.x - hi.y, to: hi.x - lo.y + 1 },
+        }
+    }
+
+    fn contains(&self, u: isize, v: isize) -> bool {
+        (self.u.from..self.u.to).contains(&u) && (self.v.from..self.v.to).contains(&v)
+    }
+
+    // positions of the rectangle inside the box between two corners, only
+    // a u and v of the same parity map back to whole coordinates
+    fn points(self, lo: Position, hi: Position) -> impl Iterator<Item = Position> {
+        // skip the columns where the box leaves no room for any v
+        let u_from = self.u.from.max(self.v.from + 2 * lo.y).max(2 * lo.x - self.v.to + 1);
+        let u_to = self.u.to.min(2 * hi.x - self.v.from + 1).min(self.v.to + 2 * hi.y);
+
+        (u_from..u_to).flat_map(move |u| {
+            let from = self.v.from.max(2 * lo.x - u).max(u - 2 * hi.y);
+            let to = (self.v.to - 1).min(2 * hi.x - u).min(u - 2 * lo.y);
+            let from = from + (from - u).rem_euclid(2);
+
+            (from..=to)
+                .step_by(2)
+                .map(move |v| Position { x: (u + v) / 2, y: (u - v) / 2 })
+        })
+    }
+}
+
+struct Coverage {
+    squares: Vec<Rect>,
+}
+
+impl Coverage {
+    fn new(beacons: &[Data]) -> Self {
+        let squares = beacons
+            .iter()
+            .map(|d| Rect::around(d.sensor, d.sensor.manhattan(&d.beacon) as isize))
+            .collect();
+
+        Coverage { squares }
+    }
+
+    // the parts of the box between two corners that no sensor covers,
+    // the square edges split the rotated space into cells that are either
+    // wholly covered or wholly uncovered, so only those cells are checked
+    fn uncovered(&self, lo: Position, hi: Position) -> Vec<Rect> {
+        let area = Rect::bounding(lo, hi);
+
+        let cuts = |range: fn(&Rect) -> Range| {
+            let mut cuts: Vec<_> = self.squares
+                .iter()
+                .chain([&area])
+                .flat_map(|r| [range(r).from, range(r).to])
+                .filter(|&c| (range(&area).from..=range(&area).to).contains(&c))
+                .collect();
+            cuts.sort_unstable();
+            cuts.dedup();
+
+            cuts
+        };
+        let u_cuts = cuts(|r| r.u);
+        let v_cuts = cuts(|r| r.v);
+
+        let mut cells = Vec::new();
+        for u in u_cuts.windows(2) {
+            for v in v_cuts.windows(2) {
+                let cell = Rect {
+                    u: Range { from: u[0], to: u[1] },
+                    v: Range { from: v[0], to: v[1] },
+                };
+
+                if self.squares.iter().all(|s| !s.contains(u[0], v[0]))
+                    && cell.points(lo, hi).next().is_some() {
+                    cells.push(cell);
+                }
+            }
+        }
+
+        cells
+    }
+
+    fn uncovered_points(&self, lo: Position, hi: Position) -> impl Iterator<Item = Position> {
+        self.uncovered(lo, hi)
+            .into_iter()
+            .flat_map(move |cell| cell.points(lo, hi))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tile {
+    Unseen,
+    Covered,
+    Gap,
+    Sensor,
+    Beacon,
+    Distress,
+}
+
+impl Tile {
+    fn symbol(self) -> char {
+        match self {
+            Tile::Unseen => '.',
+            Tile::Covered => '#',
+            Tile::Gap => '+',
+            Tile::Sensor => 'S',
+            Tile::Beacon => 'B',
+            Tile::Distress => 'X',
+        }
+    }
+
+    fn color(self) -> [u8; 3] {
+        match self {
+            Tile::Unseen => [24, 24, 32],
+            Tile::Covered => [60, 90, 150],
+            Tile::Gap => [230, 170, 50],
+            Tile::Sensor => [90, 190, 90],
+            Tile::Beacon => [230, 230, 230],
+            Tile::Distress => [230, 60, 60],
+        }
+    }
+}
+
+// the viewport between two corners split into square blocks of positions,
+// a block with any uncovered position next to covered ones counts as a gap
+fn coverage_map(
+    beacons: &[Data],
+    coverage: &Coverage,
+    distress: Option<Position>,
+    lo: Position,
+    hi: Position,
+    columns: usize,
+) -> Vec<Vec<Tile>> {
+    let block = ((hi.x - lo.x + 1) as usize).div_ceil(columns).max(1) as isize;
+
+    (lo.y..=hi.y)
+        .step_by(block as usize)
+        .map(|y| {
+            (lo.x..=hi.x)
+                .step_by(block as usize)
+                .map(|x| {
+                    let from = Position { x, y };
+                    let to = Position { x: (x + block - 1).min(hi.x), y: (y + block - 1).min(hi.y) };
+                    let inside = |p: &Position| (from.x..=to.x).contains(&p.x)
+                        && (from.y..=to.y).contains(&p.y);
+                    let closest = |p: &Position| Position {
+                        x: p.x.clamp(from.x, to.x),
+                        y: p.y.clamp(from.y, to.y),
+                    };
+
+                    if distress.is_some_and(|d| inside(&d)) {
+                        Tile::Distress
+                    } else if beacons.iter().any(|d| inside(&d.sensor)) {
+                        Tile::Sensor
+                    } else if beacons.iter().any(|d| inside(&d.beacon)) {
+                        Tile::Beacon
+                    } else if beacons.iter().all(|d| {
+                        closest(&d.sensor).manhattan(&d.sensor) > d.sensor.manhattan(&d.beacon)
+                    }) {
+                        Tile::Unseen
+                    } else if coverage.uncovered(from, to).is_empty() {
+                        Tile::Covered
+                    } else {
+                        Tile::Gap
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn write_ppm(tiles: &[Vec<Tile>], path: &Path) -> io::Result<()> {
+    let (width, height) = (tiles[0].len(), tiles.len());
+
+    let mut data = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    for tile in tiles.iter().flatten() {
+        data.extend_from_slice(&tile.color());
+    }
+
+    fs::write(path, data)
+}
+
 fn main() {
-    let path = Path::new("inputs/day15.txt");
+    let args: Vec<String> = env::args().skip(1).collect();
+    let option = |name: &str| args
+        .iter()
+        .position(|a| a == name)
+        .map(|i| args.get(i + 1).and_then(|v| v.parse::<isize>().ok()).unwrap_or_else(|| {
+            eprintln!("{} expects a number", name);
+            process::exit(1);
+        }));
+
+    let input = match args.iter().position(|a| a == "--input") {
+        Some(i) => args.get(i + 1).map(String::as_str).unwrap_or_else(|| {
+            eprintln!("--input expects a file");
+            process::exit(1);
+        }),
+        None => "inputs/day15.txt",
+    };
+
+    let path = Path::new(input);
     let reader = match File::open(path) {
         Err(e) => panic!("could not open input file at {}: {}", path.display(), e),
         Ok(file) => BufReader::new(file),
@@ -124,36 +387,120 @@ fn main() {
         );
     }
 
-    const ROW_TO_CHECK: isize = 2_000_000;
+    let lower_bound = option("--lower").unwrap_or(0);
+    let upper_bound = option("--upper").unwrap_or(4_000_000);
+    if lower_bound > upper_bound {
+        eprintln!("--lower {} is above --upper {}", lower_bound, upper_bound);
+        process::exit(1);
+    }
 
-    let ranges = get_hor_ranges(ROW_TO_CHECK, &beacons, None);
+    // every option takes a value, whatever is left names the query
+    let mut positional = Vec::new();
+    let mut rest = args.iter().map(String::as_str);
+    while let Some(arg) = rest.next() {
+        if arg.starts_with("--") {
+            rest.next();
+        } else {
+            positional.push(arg);
+        }
+    }
 
-    let num_pos = ranges
-        .into_iter()
-        .fold(0, |acc, rng| acc + (rng.to - rng.from - 1));
+    let query = match positional.first().copied() {
+        Some("row") => Some(Line::Row as fn(isize) -> Line),
+        Some("column") => Some(Line::Column as fn(isize) -> Line),
+        _ => None,
+    };
+    if let Some(line) = query {
+        let Some(at) = positional.get(1).and_then(|a| a.parse().ok()) else {
+            eprintln!("usage: day15 row|column <number> [--lower N --upper N]");
+            process::exit(1);
+        };
+
+        let bounded = args.iter().any(|a| a == "--lower" || a == "--upper");
+        let bounds = bounded.then_some(Range { from: lower_bound, to: upper_bound });
+        for range in get_ranges(line(at), &beacons, bounds) {
+            println!("{}..={}", range.from, range.to - 1);
+        }
+        return;
+    }
+
+    if positional.first() == Some(&"map") {
+        let corners: Option<Vec<isize>> = positional[1..].iter().map(|a| a.parse().ok()).collect();
+        let Some(&[x0, y0, x1, y1]) = corners.as_deref() else {
+            eprintln!("usage: day15 map <x0> <y0> <x1> <y1> [--columns N] [--image FILE]");
+            process::exit(1);
+        };
+
+        let lo = Position { x: x0.min(x1), y: y0.min(y1) };
+        let hi = Position { x: x0.max(x1), y: y0.max(y1) };
+        let columns = option("--columns").unwrap_or(80).max(1) as usize;
+
+        let coverage = Coverage::new(&beacons);
+        let distress = coverage
+            .uncovered_points(
+                Position { x: lower_bound, y: lower_bound },
+                Position { x: upper_bound, y: upper_bound },
+            )
+            .next();
+        let tiles = coverage_map(&beacons, &coverage, distress, lo, hi, columns);
+
+        match args.iter().position(|a| a == "--image").and_then(|i| args.get(i + 1)) {
+            Some(file) => {
+                if let Err(e) = write_ppm(&tiles, Path::new(file)) {
+                    eprintln!("could not write image to {}: {}", file, e);
+                    process::exit(1);
+                }
+            },
+            None => {
+                for row in &tiles {
+                    println!("{}", row.iter().map(|t| t.symbol()).collect::<String>());
+                }
+            },
+        }
+        return;
+    }
+
+    let row_to_check = option("--row").unwrap_or(2_000_000);
+
+    let num_pos = count_no_beacon(Line::Row(row_to_check), &beacons);
 
     println!("[Part 1] The number of positions that cannot contain \
-              a beacon in the row {} is {}", ROW_TO_CHECK, num_pos);
+              a beacon in the row {} is {}", row_to_check, num_pos);
 
-    const LOWER_BOUND: isize = 0;
-    const UPPER_BOUND: isize = 4_000_000;
+    let x_multiplier = option("--multiplier").unwrap_or(4_000_000);
 
-    const X_MULTIPLIER: isize = 4_000_000;
+    let lo = Position { x: lower_bound, y: lower_bound };
+    let hi = Position { x: upper_bound, y: upper_bound };
+    // only a few candidates are looked at, an open area can hold trillions
+    const MAX_CANDIDATES: usize = 10;
+    let candidates: Vec<_> = Coverage::new(&beacons)
+        .uncovered_points(lo, hi)
+        .take(MAX_CANDIDATES + 1)
+        .collect();
 
-    let tun_freq = (LOWER_BOUND..=UPPER_BOUND)
-        .map(|y| {
-            (get_hor_ranges(
-                y, &beacons,
-                Some( Range { from : LOWER_BOUND, to : UPPER_BOUND })
-            ), y)
-        })
-        .skip_while(|(ranges, _)| ranges.len() <= 1)
-        .map(|(ranges, y)| {
-            let x = ranges.first().unwrap().to;
+    match candidates[..] {
+        [] => println!("[Part 2] Every position from {} to {} is covered by a sensor, \
+                        there is no distress beacon", lower_bound, upper_bound),
+        [distress] => {
+            let tun_freq = distress.x * x_multiplier + distress.y;
 
-            x * X_MULTIPLIER + y
-        }).next().unwrap();
+            println!("[Part 2] The tuning frequency of the distress beacon \
+                      is {}", tun_freq);
+        },
+        _ => {
+            let count = if candidates.len() > MAX_CANDIDATES {
+                format!("more than {}", MAX_CANDIDATES)
+            } else {
+                candidates.len().to_string()
+            };
+            let shown: Vec<_> = candidates
+                .iter()
+                .take(3)
+                .map(|p| format!("x={}, y={}", p.x, p.y))
+                .collect();
 
-    println!("[Part 2] The tuning frequency of the distress beacon \
-              is {}", tun_freq);
+            println!("[Part 2] The distress beacon could be at {} positions, \
+                      such as {}", count, shown.join("; "));
+        },
+    }
 }
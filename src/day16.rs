@@ -1,17 +1,17 @@
-use std::cmp::Reverse;
-use std::collections::{BinaryHeap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, VecDeque};
+use std::env;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::path::Path;
-
-use itertools::Itertools;
+use std::process;
 
 type Index = usize;
 type Indexes = HashMap<String, Index>;
 
 #[derive(Debug)]
 struct Valve {
+    name: String,
     flow_rate: u32,
     leads_to: Vec<Index>,
 }
@@ -46,8 +46,9 @@ where I: Iterator<Item = std::io::Result<String>> {
             .map(|s| s.trim_end_matches(',').to_string())
             .collect();
 
-        indexes.insert(name, i);
+        indexes.insert(name.clone(), i);
         valves.push(Valve {
+            name,
             flow_rate,
             leads_to: Vec::with_capacity(leads_to.len())
         });
@@ -63,85 +64,339 @@ where I: Iterator<Item = std::io::Result<String>> {
     (valves, indexes)
 }
 
-fn find_min(from: Index, to: Index, valves: &Valves) -> u32 {
-    let mut queue = BinaryHeap::new();
-    let mut visited = HashSet::new();
+type Distances = Vec<Vec<Option<u32>>>;
 
+fn all_distances(valves: &Valves) -> Distances {
     const MOVE_COST: u32 = 1;
 
-    queue.push((Reverse(0), from));
-    visited.insert(from);
+    let mut dists = vec![vec![None; valves.len()]; valves.len()];
+    for (from, valve) in valves.iter().enumerate() {
+        dists[from][from] = Some(0);
+        for &to in &valve.leads_to {
+            if to != from {
+                dists[from][to] = Some(MOVE_COST);
+            }
+        }
+    }
+
+    for via in 0..valves.len() {
+        let via_row = dists[via].clone();
 
-    while let Some((Reverse(cost), cur)) = queue.pop() {
-        if cur == to {
-            return cost;
+        for row in dists.iter_mut() {
+            let Some(first) = row[via] else {
+                continue;
+            };
+
+            for (dist, second) in row.iter_mut().zip(&via_row) {
+                if let Some(second) = second {
+                    let through = first + second;
+                    if dist.is_none_or(|direct| through < direct) {
+                        *dist = Some(through);
+                    }
+                }
+            }
         }
+    }
+
+    dists
+}
+
+fn tunnel_path(from: Index, to: Index, valves: &Valves, dists: &Distances) -> Vec<Index> {
+    let mut path = Vec::new();
+    let mut cur = from;
+
+    while cur != to {
+        let left = dists[cur][to].expect("valves to walk between are not connected");
+        cur = *valves[cur].leads_to
+            .iter()
+            .find(|&&next| dists[next][to] == Some(left - 1))
+            .unwrap();
+        path.push(cur);
+    }
+
+    path
+}
+
+// flowing valves come first, followed by the start if it has no flow
+struct Network {
+    nodes: Vec<Index>,
+    flow_rates: Vec<u32>,
+    dists: Vec<Vec<u32>>,
+    flowing: usize,
+    start: usize,
+}
 
-        valves[cur].leads_to
+impl Network {
+    fn new(valves: &Valves, start_idx: Index, all_dists: &Distances) -> (Self, Vec<Index>) {
+        let (mut nodes, unreachable): (Vec<Index>, Vec<Index>) = (0..valves.len())
+            .filter(|&index| valves[index].flow_rate > 0)
+            .partition(|&index| all_dists[start_idx][index].is_some());
+        let flowing = nodes.len();
+
+        let start = match nodes.iter().position(|&index| index == start_idx) {
+            Some(start) => start,
+            None => {
+                nodes.push(start_idx);
+                nodes.len() - 1
+            },
+        };
+
+        let flow_rates = nodes.iter().map(|&index| valves[index].flow_rate).collect();
+        let dists = nodes
             .iter()
-            .filter(|&&next| visited.insert(next))
-            .for_each(|&next| queue.push((Reverse(cost + MOVE_COST), next)));
+            .map(|&from| nodes
+                .iter()
+                .map(|&to| all_dists[from][to].unwrap_or(u32::MAX))
+                .collect())
+            .collect();
+
+        (Network { nodes, flow_rates, dists, flowing, start }, unreachable)
     }
 
-    panic!("No path found between nodes {from} and {to}!");
+    fn all_flowing(&self) -> u64 {
+        if self.flowing == 64 { u64::MAX } else { (1 << self.flowing) - 1 }
+    }
 }
 
-fn distances<'a, I>(
-    indexes: I,
-    start_idx: &'a Index,
-    valves: &Valves
-) -> HashMap<(Index, Index), u32>
-where I: Iterator<Item = &'a Index> + Clone {
-    indexes
-        .chain([start_idx])
-        .tuple_combinations()
-        .fold(HashMap::new(), |mut acc, (&from, &to)| {
-            let distance = find_min(from, to, valves);
+struct State {
+    node: usize,
+    time_left: u32,
+    flow: u32,
+    parent: Option<usize>,
+}
 
-            acc.insert((from, to), distance);
-            acc.insert((to, from), distance);
+struct Simulation {
+    states: Vec<State>,
+    // for every set of opened valves, the state releasing the most pressure
+    best: HashMap<u64, usize>,
+}
 
-            acc
-        })
+impl Simulation {
+    fn flow(&self, open: u64) -> u32 {
+        self.states[self.best[&open]].flow
+    }
 }
 
-fn simulate(
-    valves: &Valves,
-    start_idx: Index,
-    time_limit: u32,
-    flowing: &BTreeSet<Index>,
-    dists: &HashMap<(Index, Index), u32>
-) -> HashMap::<BTreeSet<Index>, u32> {
+fn simulate(network: &Network, time_limit: u32) -> Simulation {
     const OPEN_COST: u32 = 1;
 
-    let mut result = HashMap::<BTreeSet<Index>, u32>::new();
-    let mut queue = VecDeque::from([(start_idx, BTreeSet::new(), time_limit, 0)]);
+    let mut states = vec![State { node: network.start, time_left: time_limit, flow: 0,
+                                  parent: None }];
+    let mut best = HashMap::<u64, usize>::new();
+    let mut queue = VecDeque::from([(0, 0_u64)]);
+
+    while let Some((id, open)) = queue.pop_front() {
+        let State { node: cur, time_left, flow, .. } = states[id];
+
+        best
+            .entry(open)
+            .and_modify(|v| if states[*v].flow < flow { *v = id })
+            .or_insert(id);
 
-    while let Some((cur, open, time_left, flow)) = queue.pop_front() {
-        result
-            .entry(open.clone())
-            .and_modify(|v| *v = (*v).max(flow))
-            .or_insert(flow);
+        let mut closed = network.all_flowing() & !open;
 
-        let closed = flowing.difference(&open);
+        while closed != 0 {
+            let next = closed.trailing_zeros() as usize;
+            closed &= closed - 1;
 
-        for &next in closed {
-            let time_to_open = dists[&(cur, next)] + OPEN_COST;
+            let time_to_open = network.dists[cur][next].saturating_add(OPEN_COST);
             if time_to_open >= time_left {
                 continue;
             }
 
             let new_time_left = time_left - time_to_open;
-            let new_flow = flow + valves[next].flow_rate * new_time_left;
+            let new_flow = flow + network.flow_rates[next] * new_time_left;
+
+            states.push(State { node: next, time_left: new_time_left, flow: new_flow,
+                                parent: Some(id) });
+            queue.push_back((states.len() - 1, open | 1 << next));
+        }
+    }
+
+    Simulation { states, best }
+}
+
+// splitting valves between agents goes through tables with an entry for every subset of them
+const MAX_SPLIT_FLOWING: usize = 20;
+
+fn max_with_agents(simulation: &Simulation, flowing: usize, agents: usize) -> (u32, Vec<u64>) {
+    if agents == 1 {
+        let open = *simulation.best.keys().max_by_key(|&&open| simulation.flow(open)).unwrap();
+        return (simulation.flow(open), vec![open]);
+    }
+    assert!(flowing <= MAX_SPLIT_FLOWING, "too many flowing valves to split between agents");
+
+    let full = (1_usize << flowing) - 1;
+
+    // best[mask] is the most pressure one agent releases opening only valves from mask,
+    // best_open[mask] is the exact set of valves it opens for that
+    let mut best = vec![0; full + 1];
+    let mut best_open = vec![0_u64; full + 1];
+    for &open in simulation.best.keys() {
+        best[open as usize] = simulation.flow(open);
+        best_open[open as usize] = open;
+    }
+
+    for bit in 0..flowing {
+        for mask in 0..=full {
+            if mask & 1 << bit != 0 && best[mask ^ 1 << bit] > best[mask] {
+                best[mask] = best[mask ^ 1 << bit];
+                best_open[mask] = best_open[mask ^ 1 << bit];
+            }
+        }
+    }
+
+    let mut team = best.clone();
+    let mut choices = Vec::new();
+    for _ in 1..agents {
+        let (next, choice): (Vec<_>, Vec<_>) = (0..=full)
+            .map(|mask| {
+                let mut result = (team[mask], 0);
+                let mut sub = mask;
+
+                while sub != 0 {
+                    if best[sub] + team[mask ^ sub] > result.0 {
+                        result = (best[sub] + team[mask ^ sub], sub);
+                    }
+                    sub = (sub - 1) & mask;
+                }
+
+                result
+            })
+            .unzip();
+
+        team = next;
+        choices.push(choice);
+    }
+
+    let mut mask = full;
+    let mut opened = Vec::with_capacity(agents);
+    for choice in choices.iter().rev() {
+        let sub = choice[mask];
+        opened.push(best_open[sub]);
+        mask ^= sub;
+    }
+    opened.push(best_open[mask]);
+    opened.reverse();
+
+    (team[full], opened)
+}
 
-            let mut new_open = open.clone();
-            new_open.insert(next);
+struct Step {
+    valve: Index,
+    arrived: u32,
+    opened: u32,
+    released: u32,
+}
+
+fn schedule(simulation: &Simulation, network: &Network, open: u64,
+            time_limit: u32) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let mut id = simulation.best[&open];
+
+    while let Some(parent) = simulation.states[id].parent {
+        let state = &simulation.states[id];
+        let opened = time_limit - state.time_left;
+
+        steps.push(Step {
+            valve: network.nodes[state.node],
+            arrived: opened - 1,
+            opened,
+            released: state.flow,
+        });
+        id = parent;
+    }
+    steps.reverse();
+
+    steps
+}
+
+fn agent_name(agent: usize) -> String {
+    match agent {
+        0 => "You".to_string(),
+        1 => "The elephant".to_string(),
+        n => format!("Elephant {}", n),
+    }
+}
+
+fn list_names(names: &[&str]) -> String {
+    match names {
+        [single] => single.to_string(),
+        [first, second] => format!("{} and {}", first, second),
+        [rest @ .., last] => format!("{}, and {}", rest.join(", "), last),
+        [] => String::new(),
+    }
+}
 
-            queue.push_back((next, new_open, new_time_left, new_flow));
+fn print_schedules(schedules: &[Vec<Step>], network: &Network, valves: &Valves,
+                   dists: &Distances, time_limit: u32) {
+    for (agent, steps) in schedules.iter().enumerate() {
+        println!("Agent {} opens:", agent + 1);
+        for step in steps {
+            println!("  valve {} reached at minute {}, opened at minute {}, \
+                      releasing {} pressure in total",
+                     valves[step.valve].name, step.arrived, step.opened, step.released);
         }
     }
+    println!();
+
+    // minute by minute actions of every agent, moving through the tunnels
+    let actions: Vec<Vec<String>> = schedules
+        .iter()
+        .map(|steps| {
+            let mut actions = Vec::new();
+            let mut cur = network.nodes[network.start];
+
+            for step in steps {
+                for next in tunnel_path(cur, step.valve, valves, dists) {
+                    actions.push(format!("move to valve {}", valves[next].name));
+                }
+                actions.push(format!("open valve {}", valves[step.valve].name));
+                cur = step.valve;
+            }
+
+            actions
+        })
+        .collect();
+
+    let mut open: Vec<(u32, Index)> = schedules
+        .iter()
+        .flatten()
+        .map(|step| (step.opened, step.valve))
+        .collect();
+    open.sort_unstable();
+
+    for minute in 1..=time_limit {
+        println!("== Minute {} ==", minute);
 
-    result
+        let mut names: Vec<&str> = open
+            .iter()
+            .filter(|&&(opened, _)| opened < minute)
+            .map(|&(_, valve)| valves[valve].name.as_str())
+            .collect();
+        names.sort_unstable();
+        let pressure: u32 = open
+            .iter()
+            .filter(|&&(opened, _)| opened < minute)
+            .map(|&(_, valve)| valves[valve].flow_rate)
+            .sum();
+
+        match names.len() {
+            0 => println!("No valves are open."),
+            1 => println!("Valve {} is open, releasing {} pressure.", names[0], pressure),
+            _ => println!("Valves {} are open, releasing {} pressure.",
+                          list_names(&names), pressure),
+        }
+
+        for (agent, actions) in actions.iter().enumerate() {
+            if let Some(action) = actions.get(minute as usize - 1) {
+                println!("{} {}.", agent_name(agent),
+                         if agent == 0 { action.clone() } else { action.replacen(' ', "s ", 1) });
+            }
+        }
+
+        println!();
+    }
 }
 
 fn main() {
@@ -153,32 +408,78 @@ fn main() {
 
     let (valves, indexes) = parse_valves(reader.lines());
 
-    let flowing: BTreeSet<_> = valves
-        .iter()
-        .enumerate()
-        .filter(|(_, valve)| valve.flow_rate > 0)
-        .map(|(index, _)| index)
-        .collect();
+    let start_idx = indexes["AA"];
+    let dists = all_distances(&valves);
+    let (network, unreachable) = Network::new(&valves, start_idx, &dists);
+
+    if network.flowing > 64 {
+        eprintln!("{} valves have flow, at most 64 fit in a set of opened valves", network.flowing);
+        process::exit(1);
+    }
+
+    if !unreachable.is_empty() {
+        let names: Vec<_> = unreachable.iter().map(|&i| valves[i].name.as_str()).collect();
+        eprintln!("Valves {} can't be reached from AA and are ignored", names.join(", "));
+    }
 
-    let start_idx = indexes[&"AA".to_string()];
+    let args: Vec<String> = env::args().skip(1).collect();
+    let option = |name: &str| args
+        .iter()
+        .position(|a| a == name)
+        .map(|i| args
+            .get(i + 1)
+            .and_then(|v| v.parse().ok())
+            .filter(|&v: &usize| v > 0)
+            .unwrap_or_else(|| {
+                eprintln!("{} expects a positive number", name);
+                process::exit(1);
+            }));
+
+    let print_schedule = args.iter().any(|a| a == "--schedule");
+
+    let (agents, time_limit) = (option("--agents"), option("--time"));
+    if print_schedule || agents.is_some() || time_limit.is_some() {
+        let agents = agents.unwrap_or(1);
+        let time_limit = time_limit.unwrap_or(30) as u32;
+        if agents > 1 && network.flowing > MAX_SPLIT_FLOWING {
+            eprintln!("{} valves have flow, at most {} can be split between agents",
+                      network.flowing, MAX_SPLIT_FLOWING);
+            process::exit(1);
+        }
 
-    let dists = distances(flowing.iter(), &start_idx, &valves);
+        let simulation = simulate(&network, time_limit);
+        let (result, opened) = max_with_agents(&simulation, network.flowing, agents);
+        println!("The most pressure {} agents can release in {} minutes is {}",
+                 agents, time_limit, result);
+
+        if print_schedule {
+            let schedules: Vec<_> = opened
+                .iter()
+                .map(|&open| schedule(&simulation, &network, open, time_limit))
+                .collect();
+            print_schedules(&schedules, &network, &valves, &dists, time_limit);
+        }
+        return;
+    }
 
     const PART1_LIMIT: u32 = 30;
-    let result = *simulate(&valves, start_idx, PART1_LIMIT, &flowing, &dists)
-        .values()
+    let simulation = simulate(&network, PART1_LIMIT);
+    let result = simulation.best
+        .keys()
+        .map(|&open| simulation.flow(open))
         .max()
         .unwrap();
     println!("[Part 1] The most pressure you can release is {}", result);
 
+    if network.flowing > MAX_SPLIT_FLOWING {
+        eprintln!("[Part 2] {} valves have flow, at most {} can be split with an elephant",
+                  network.flowing, MAX_SPLIT_FLOWING);
+        process::exit(1);
+    }
+
     const PART2_LIMIT: u32 = 26;
-    let result = simulate(&valves, start_idx, PART2_LIMIT, &flowing, &dists)
-        .iter()
-        .combinations(2)
-        .filter(|comb| comb[0].0.is_disjoint(comb[1].0))
-        .map(|comb| comb[0].1 + comb[1].1)
-        .max()
-        .unwrap();
+    let simulation = simulate(&network, PART2_LIMIT);
+    let (result, _) = max_with_agents(&simulation, network.flowing, 2);
     println!("[Part 2] The most pressure you can release with an elephant \
               helping you is {}", result);
 }
@@ -1,7 +1,9 @@
 use std::collections::HashMap;
-use std::fs::read_to_string;
-use std::ops::Range;
+use std::env;
+use std::fs::{self, read_to_string};
+use std::io;
 use std::path::Path;
+use std::process;
 
 #[derive(Debug)]
 enum Move {
@@ -9,232 +11,390 @@ enum Move {
     Right,
 }
 
-type Position = (usize, usize);
+const SHAPES: &str = "\
+####
 
-const SHAPES: [&[Position]; 5] = [
-    &[(0, 0), (1, 0), (2, 0), (3, 0)],
-    &[(0, 1), (1, 0), (1, 1), (2, 1), (1, 2)],
-    &[(0, 0), (1, 0), (2, 0), (2, 1), (2, 2)],
-    &[(0, 0), (0, 1), (0, 2), (0, 3)],
-    &[(0, 0), (1, 0), (0, 1), (1, 1)]
-];
+.#.
+###
+.#.
 
-const WIDTH: usize = 7;
-type Chamber = Vec<[bool; WIDTH]>;
+..#
+..#
+###
 
-fn intersects(rock: &[(usize, usize)], pos: Position, chamber: &Chamber) -> bool {
-    rock.iter().any(|p| {
-        let x = pos.0 + p.0;
-        let y = pos.1 + p.1;
+#
+#
+#
+#
 
-        x >= 7 || chamber[y][x]
-    })
+##
+##
+";
+
+const MAX_WIDTH: usize = 64;
+
+// rows of a rock from the bottom up, the leftmost column is the lowest bit
+#[derive(Clone)]
+struct Shape {
+    rows: Vec<u64>,
+    width: usize,
 }
 
-fn place(rock: &[(usize, usize)], pos: Position, chamber: &mut Chamber) -> usize {
-    for p in rock {
-        let x = pos.0 + p.0;
-        let y = pos.1 + p.1;
+struct Config {
+    shapes: Vec<Shape>,
+    width: usize,
+    spawn_x: usize,
+    spawn_y: usize,
+}
 
-        chamber[y][x] = true;
+impl Config {
+    fn parse_shapes(input: &str) -> Vec<Shape> {
+        input
+            .split("\n\n")
+            .filter(|block| !block.trim().is_empty())
+            .map(|block| {
+                let rows: Vec<u64> = block
+                    .lines()
+                    .filter(|l| !l.trim().is_empty())
+                    .rev()
+                    .map(|row| row
+                        .trim()
+                        .bytes()
+                        .enumerate()
+                        .fold(0, |mask, (x, b)| match b {
+                            b'#' if x < MAX_WIDTH => mask | 1 << x,
+                            b'#' => panic!("rock shape is wider than {} units", MAX_WIDTH),
+                            b'.' => mask,
+                            _ => panic!("unexpected symbol in a rock shape: {}", b as char),
+                        }))
+                    .collect();
+                let width = rows.iter().map(|r| 64 - r.leading_zeros() as usize).max().unwrap();
+
+                Shape { rows, width }
+            })
+            .collect()
     }
 
-    extend_for_next(chamber)
-}
+    fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.width > MAX_WIDTH {
+            return Err(format!("chamber width should be from 1 to {}", MAX_WIDTH));
+        }
 
-fn extend_for_next(chamber: &mut Chamber) -> usize {
-    let mut y = 0;
+        if self.shapes.is_empty() || self.shapes.iter().any(|s| s.width == 0) {
+            return Err("every rock shape should have at least one '#'".to_string());
+        }
 
-    for ln in chamber.iter().rev() {
-        if ln.iter().any(|&x| x) {
-            break;
-        } else {
-            y += 1;
+        for shape in &self.shapes {
+            if self.spawn_x + shape.width > self.width {
+                return Err(format!("a rock {} units wide does not fit the chamber when \
+                                    spawned {} units from the left wall",
+                                   shape.width, self.spawn_x));
+            }
         }
+
+        Ok(())
     }
 
-    let highest = chamber.len() - (y + 1);
+    fn full_row(&self) -> u64 {
+        u64::MAX >> (MAX_WIDTH - self.width)
+    }
+}
 
-    const MAX_REQUIRED: usize = 7;
-    let ext = MAX_REQUIRED - y;
-    chamber.resize(chamber.len() + ext, [false; WIDTH]);
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            shapes: Config::parse_shapes(SHAPES),
+            width: 7,
+            spawn_x: 2,
+            spawn_y: 3,
+        }
+    }
+}
 
-    highest
+struct Tower<'a> {
+    config: &'a Config,
+    // rows from the floor up, with free rows reserved above the highest rock
+    rows: Vec<u64>,
+    height: usize,
+    rocks: usize,
+    jet: usize,
+    // shape index and position of every settled rock
+    placed: Vec<(usize, usize, usize)>,
 }
 
-const SPAWN_X: usize = 2;
-const SPAWN_Y: usize = 3;
-
-fn place_rock(
-    shape: &[Position],
-    highest: usize,
-    jet_index: &mut usize,
-    chamber: &mut Chamber,
-    moves: &Vec<Move>
-) -> usize {
-    let mut x = SPAWN_X;
-    let mut y = highest;
-
-    loop {
-        let mv = &moves[*jet_index % moves.len()];
-        *jet_index += 1;
-
-        x = match mv {
-            Move::Left if x != 0 => {
-                let next_x = x - 1;
-
-                if !intersects(shape, (next_x, y), chamber) {
-                    next_x
-                } else {
-                    x
+impl<'a> Tower<'a> {
+    fn new(config: &'a Config) -> Self {
+        Tower { config, rows: Vec::new(), height: 0, rocks: 0, jet: 0, placed: Vec::new() }
+    }
+
+    fn intersects(&self, shape: &Shape, x: usize, y: usize) -> bool {
+        x + shape.width > self.config.width
+            || shape.rows
+                .iter()
+                .enumerate()
+                .any(|(dy, row)| self.rows[y + dy] & row << x != 0)
+    }
+
+    fn spawn(&self) -> (usize, usize, usize) {
+        (self.rocks % self.config.shapes.len(), self.config.spawn_x,
+         self.height + self.config.spawn_y)
+    }
+
+    fn drop_rock(&mut self, moves: &[Move]) {
+        let (index, mut x, mut y) = self.spawn();
+        let shape = &self.config.shapes[index];
+
+        self.rows.resize(y + shape.rows.len(), 0);
+
+        loop {
+            let mv = &moves[self.jet % moves.len()];
+            self.jet += 1;
+
+            let next_x = match mv {
+                Move::Left => x.checked_sub(1),
+                Move::Right => Some(x + 1),
+            };
+            if let Some(next_x) = next_x.filter(|&nx| !self.intersects(shape, nx, y)) {
+                x = next_x;
+            }
+
+            if y == 0 || self.intersects(shape, x, y - 1) {
+                break;
+            } else {
+                y -= 1;
+            }
+        }
+
+        for (dy, row) in shape.rows.iter().enumerate() {
+            self.rows[y + dy] |= row << x;
+        }
+
+        self.height = self.height.max(y + shape.rows.len());
+        self.rocks += 1;
+        self.placed.push((index, x, y));
+    }
+
+    // shape index of the rock occupying every cell, rows from the floor up
+    fn cells(&self, height: usize) -> Vec<Vec<Option<usize>>> {
+        let mut cells = vec![vec![None; self.config.width]; height];
+
+        for &(index, x, y) in &self.placed {
+            for (dy, row) in self.config.shapes[index].rows.iter().enumerate() {
+                for dx in 0..MAX_WIDTH {
+                    if row >> dx & 1 == 1 && y + dy < height {
+                        cells[y + dy][x + dx] = Some(index);
+                    }
                 }
-            },
-            Move::Right if x != WIDTH - 1 => {
-                let next_x = x + 1;
+            }
+        }
 
-                if !intersects(shape, (next_x, y), chamber) {
-                    next_x
+        cells
+    }
+
+    fn render(&self, from: usize, to: Option<usize>, with_falling: bool) -> String {
+        let (index, x, y) = self.spawn();
+        let shape = &self.config.shapes[index];
+        let falling = |row: usize, col: usize| with_falling
+            && row >= y && row < y + shape.rows.len()
+            && col >= x && shape.rows[row - y] >> (col - x) & 1 == 1;
+
+        let top = if with_falling { y + shape.rows.len() } else { self.height };
+        let to = to.unwrap_or(top).min(top);
+
+        let mut out = String::new();
+        for row in (from..to).rev() {
+            out.push('|');
+            for col in 0..self.config.width {
+                out.push(if falling(row, col) {
+                    '@'
+                } else if self.rows.get(row).is_some_and(|r| r >> col & 1 == 1) {
+                    '#'
                 } else {
-                    x
-                }
-            },
-            _ => x,
-        };
+                    '.'
+                });
+            }
+            out.push_str("|\n");
+        }
 
-        if y == 0 || intersects(shape, (x, y - 1), chamber) {
-            break;
-        } else {
-            y -= 1;
+        if from == 0 {
+            out.push('+');
+            out.push_str(&"-".repeat(self.config.width));
+            out.push_str("+\n");
         }
+
+        out
     }
 
-    place(shape, (x, y), chamber)
-}
+    fn write_ppm(&self, path: &Path) -> io::Result<()> {
+        const SCALE: usize = 4;
+        const EMPTY: [u8; 3] = [24, 24, 32];
+        const PALETTE: [[u8; 3]; 8] = [
+            [230, 80, 70], [240, 170, 50], [90, 190, 90], [70, 140, 230],
+            [170, 100, 220], [60, 200, 200], [230, 120, 180], [200, 200, 200],
+        ];
+
+        let cells = self.cells(self.height);
+        let (width, height) = (self.config.width * SCALE, self.height * SCALE);
+
+        let mut data = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+        for row in cells.iter().rev() {
+            let line: Vec<u8> = row
+                .iter()
+                .flat_map(|cell| {
+                    let color = cell.map_or(EMPTY, |index| PALETTE[index % PALETTE.len()]);
+                    color.repeat(SCALE)
+                })
+                .collect();
+
+            for _ in 0..SCALE {
+                data.extend_from_slice(&line);
+            }
+        }
 
-// 7 is 3 (vertical spawn distance) + 4 (highest shape)
-const RESERVED_LINES: usize = 7;
+        fs::write(path, data)
+    }
 
-fn part_1(moves: &Vec<Move>) {
-    const NUM_ROCKS: usize = 2022;
+    // cells a falling rock could still reach moving down and sideways,
+    // listed from the top of the tower down to the deepest reachable row
+    fn surface(&self) -> Vec<u64> {
+        let full = self.config.full_row();
+        let mut reach = full;
+        let mut surface = Vec::new();
+
+        for y in (0..self.height).rev() {
+            let free = !self.rows[y] & full;
+            let mut row = reach & free;
+            loop {
+                let spread = (row | row << 1 | row >> 1) & free;
+                if spread == row {
+                    break;
+                }
+                row = spread;
+            }
+
+            if row == 0 {
+                break;
+            }
+            surface.push(row);
+            reach = row;
+        }
 
-    let mut chamber: Chamber = vec![[false; WIDTH]; RESERVED_LINES];
-    let mut highest: Option<usize> = None;
-    let mut jet = 0;
+        surface
+    }
+}
+
+fn part_1(moves: &[Move], config: &Config) {
+    const NUM_ROCKS: usize = 2022;
 
-    for i in 0..NUM_ROCKS {
-        highest = Some(place_rock(
-            SHAPES[i % SHAPES.len()],
-            highest.map_or_else(|| SPAWN_Y, |h| h + 1 + SPAWN_Y),
-            &mut jet,
-            &mut chamber,
-            moves,
-        ));
+    let mut tower = Tower::new(config);
+    for _ in 0..NUM_ROCKS {
+        tower.drop_rock(moves);
     }
 
     println!("[Part 1] After {} rocks have stopped falling \
               the tower will be {} units tall",
-             NUM_ROCKS, highest.unwrap() + 1);
+             NUM_ROCKS, tower.height);
 }
 
-const SEARCH_HEIGHT: usize = 100;
+struct Cycle {
+    start_rocks: usize,
+    start_height: usize,
+    rocks: usize,
+    height: usize,
+}
 
-fn find_cycle(
-    chamber: &mut Chamber,
-    range: Range<usize>,
-    highest: &mut usize,
-    jet_index: &mut usize,
-    moves: &Vec<Move>
-) -> (usize, usize, usize) {
-    type State = (usize, usize, [[bool; WIDTH]; SEARCH_HEIGHT]);
+fn find_cycle(tower: &mut Tower, num_rocks: usize, moves: &[Move]) -> Option<Cycle> {
+    type State = (usize, usize, Vec<u64>);
     let mut cycles = HashMap::<State, (usize, usize)>::new();
 
-    let total_count = range.end;
-
-    for i in range {
-        *highest = place_rock(
-            SHAPES[i % SHAPES.len()],
-            *highest + 1 + SPAWN_Y,
-            jet_index,
-            chamber,
-            moves,
-        );
-
-        let mut last_rows = [[false; WIDTH]; SEARCH_HEIGHT];
-        let iter = chamber[0..=*highest]
-            .iter()
-            .rev()
-            .take(SEARCH_HEIGHT);
-
-        for (i, row) in iter.enumerate() {
-            last_rows[i] = *row;
+    while tower.rocks < num_rocks {
+        tower.drop_rock(moves);
+
+        let cur_state = (tower.rocks % tower.config.shapes.len(), tower.jet % moves.len(),
+                         tower.surface());
+        if let Some((prev_height, prev_rocks)) = cycles.insert(cur_state,
+                                                               (tower.height, tower.rocks)) {
+            return Some(Cycle {
+                start_rocks: prev_rocks,
+                start_height: prev_height,
+                rocks: tower.rocks - prev_rocks,
+                height: tower.height - prev_height,
+            });
         }
+    }
 
-        let cur_state = (i % SHAPES.len(), *jet_index % moves.len(), last_rows);
-        if let Some((prev_height, prev_i)) = cycles.insert(cur_state, (*highest, i)) {
-            let height_diff = *highest - prev_height;
-            let count_diff = i - prev_i;
+    None
+}
 
-            let count_remaining = total_count - (i + 1);
-            let repeats_count = count_remaining / count_diff;
-            let remaining = count_remaining % count_diff;
-            let height_from_repeats = height_diff * repeats_count;
+fn part_2(moves: &[Move], config: &Config) {
+    const NUM_ROCKS: usize = 1_000_000_000_000;
 
-            let continue_at = i + 1;
+    let mut tower = Tower::new(config);
+    let cycle = find_cycle(&mut tower, NUM_ROCKS, moves);
 
-            return (continue_at, remaining, height_from_repeats);
-        }
+    let mut height_from_repeats = 0;
+    if let Some(cycle) = &cycle {
+        let repeats_count = (NUM_ROCKS - tower.rocks) / cycle.rocks;
+        tower.rocks += repeats_count * cycle.rocks;
+        height_from_repeats = repeats_count * cycle.height;
     }
 
-    panic!("could not find a cycle!");
-}
-
-fn part_2(moves: &Vec<Move>) {
-    const NUM_ROCKS: usize = 1_000_000_000_000;
+    while tower.rocks < NUM_ROCKS {
+        tower.drop_rock(moves);
+    }
 
-    let mut chamber: Chamber = vec![[false; WIDTH]; RESERVED_LINES];
-    let mut highest: Option<usize> = None;
-    let mut jet = 0;
+    println!("[Part 2] After {} rocks have stopped falling \
+              the tower will be {} units tall",
+             NUM_ROCKS, tower.height + height_from_repeats);
 
-    for i in 0..(SEARCH_HEIGHT + RESERVED_LINES) {
-        highest = Some(place_rock(
-            SHAPES[i % SHAPES.len()],
-            highest.map_or_else(|| SPAWN_Y, |h| h + 1 + SPAWN_Y),
-            &mut jet,
-            &mut chamber,
-            moves,
-        ));
+    if let Some(cycle) = cycle {
+        println!("  The tower repeats every {} rocks and {} units of height, \
+                  starting after rock {} at height {}",
+                 cycle.rocks, cycle.height, cycle.start_rocks, cycle.start_height);
     }
+}
 
-    let mut highest = highest.unwrap();
+fn parse_config(args: &[String]) -> Result<Config, String> {
+    let mut config = Config::default();
 
-    let (cont_at, remaining, height_from_repeats) = find_cycle(
-        &mut chamber,
-        (SEARCH_HEIGHT + RESERVED_LINES)..NUM_ROCKS,
-        &mut highest,
-        &mut jet,
-        moves
-    );
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = iter.next().ok_or_else(|| format!("{} expects a value", arg))?;
+        let number = || value.parse::<usize>()
+            .map_err(|e| format!("{} expects a number: {}", arg, e));
 
-    for i in cont_at..(cont_at + remaining) {
-        highest = place_rock(
-            SHAPES[i % SHAPES.len()],
-            highest + 1 + SPAWN_Y,
-            &mut jet,
-            &mut chamber,
-            moves,
-        );
+        match arg.as_str() {
+            "--shapes" => {
+                let shapes = read_to_string(value)
+                    .map_err(|e| format!("could not read shapes from {}: {}", value, e))?;
+                config.shapes = Config::parse_shapes(&shapes);
+            },
+            "--width" => config.width = number()?,
+            "--spawn-x" => config.spawn_x = number()?,
+            "--spawn-y" => config.spawn_y = number()?,
+            _ => return Err(format!("unknown option {}", arg)),
+        }
     }
 
-    println!("[Part 2] After {} rocks have stopped falling \
-              the tower will be {} units tall",
-             NUM_ROCKS, highest + height_from_repeats + 1);
+    config.validate()?;
+
+    Ok(config)
 }
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command_len = args.iter().position(|a| a.starts_with("--")).unwrap_or(args.len());
+    let (command, options) = args.split_at(command_len);
+
+    let config = parse_config(options).unwrap_or_else(|e| {
+        eprintln!("invalid configuration: {}", e);
+        process::exit(1);
+    });
+
     let path = Path::new("inputs/day17.txt");
     let input = read_to_string(path).unwrap();
 
-    let moves: Vec<_> = input.trim_end().bytes().into_iter().map(|mv| {
+    let moves: Vec<_> = input.trim_end().bytes().map(|mv| {
         use self::Move::*;
 
         match mv {
@@ -244,6 +404,44 @@ fn main() {
         }
     }).collect();
 
-    part_1(&moves);
-    part_2(&moves);
+    let number = |arg: Option<&String>| arg.map(|n| n.parse::<usize>().unwrap_or_else(|e| {
+        eprintln!("expected a number, got {}: {}", n, e);
+        process::exit(1);
+    }));
+
+    match command.first().map(String::as_str) {
+        None => {
+            part_1(&moves, &config);
+            part_2(&moves, &config);
+        },
+        Some("render") => {
+            let mut tower = Tower::new(&config);
+            for _ in 0..number(command.get(1)).unwrap_or(0) {
+                tower.drop_rock(&moves);
+            }
+
+            let from = number(command.get(2)).unwrap_or(0);
+            print!("{}", tower.render(from, number(command.get(3)), true));
+        },
+        Some("image") => {
+            let (Some(rocks), Some(path)) = (number(command.get(1)), command.get(2)) else {
+                eprintln!("usage: day17 image <rocks> <file.ppm> [options]");
+                process::exit(1);
+            };
+
+            let mut tower = Tower::new(&config);
+            for _ in 0..rocks {
+                tower.drop_rock(&moves);
+            }
+
+            if let Err(e) = tower.write_ppm(Path::new(path)) {
+                eprintln!("could not write image to {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        Some(other) => {
+            eprintln!("unknown command {}, expected render or image", other);
+            process::exit(1);
+        },
+    }
 }
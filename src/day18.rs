@@ -1,9 +1,11 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::env;
 use std::fs::File;
-use std::hash::Hash;
-use std::io::prelude::*;
-use std::io::BufReader;
+use std::io::{self, prelude::*};
+use std::io::{BufReader, BufWriter};
 use std::path::Path;
+use std::process;
 
 use itertools::Itertools;
 
@@ -26,27 +28,17 @@ impl Point {
     }
 }
 
-fn remove_same_elems<T>(vec: &mut Vec<T>)
-where T: Eq + Hash + Default + Copy {
-    let mut table = HashMap::<T, usize>::new();
-    vec.iter().for_each(|&c| *table.entry(c).or_default() += 1);
-
-    vec.retain(|e| table[e] == 1);
-}
-
 fn surface_area(points: &[Point]) -> usize {
-    let mut sides: Vec<_> = points
+    let cubes: HashSet<_> = points.iter().collect();
+
+    cubes
         .iter()
-        .map(|Point { x, y, z }| Point { x: x * 2, y: y * 2, z: z * 2 })
         .flat_map(|p| p.neighbours())
-        .collect();
-
-    remove_same_elems(&mut sides);
-
-    sides.len()
+        .filter(|n| !cubes.contains(n))
+        .count()
 }
 
-fn inner_area(points: &[Point]) -> usize {
+fn bounds(points: &[Point]) -> (Point, Point) {
     let (min_x, max_x) = points
         .iter()
         .map(|&Point { x, .. }| x)
@@ -66,36 +58,384 @@ fn inner_area(points: &[Point]) -> usize {
         .into_option()
         .unwrap();
 
-    let mut seen = HashMap::new();
-    for x in (min_x - 1)..=(max_x + 1) {
-        for y in (min_y - 1)..=(max_y + 1) {
-            for z in (min_z - 1)..=(max_z + 1) {
-                let p = Point { x, y, z };
-                if !points.contains(&p) {
-                    seen.insert(p, false);
+    (Point { x: min_x, y: min_y, z: min_z }, Point { x: max_x, y: max_y, z: max_z })
+}
+
+// compressed coordinates along one axis: every lava coordinate keeps a cell of
+// its own, each gap between two of them is squashed into a single cell and
+// there is one cell of padding on both ends
+struct Axis {
+    // start of each cell, followed by the end of the last one
+    starts: Vec<isize>,
+}
+
+impl Axis {
+    fn new(coords: impl Iterator<Item = isize>) -> Self {
+        let coords: Vec<_> = coords.sorted().dedup().collect();
+
+        let mut starts = vec![coords[0] - 1];
+        for (i, &c) in coords.iter().enumerate() {
+            starts.push(c);
+            if coords.get(i + 1).is_none_or(|&next| next > c + 1) {
+                starts.push(c + 1);
+            }
+        }
+        starts.push(coords[coords.len() - 1] + 2);
+
+        Axis { starts }
+    }
+
+    fn len(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    fn index(&self, coord: isize) -> isize {
+        self.starts.partition_point(|&s| s <= coord) as isize - 1
+    }
+
+    // first and last real coordinate covered by a cell
+    fn span(&self, index: isize) -> (isize, isize) {
+        (self.starts[index as usize], self.starts[index as usize + 1] - 1)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Lava,
+    Exterior,
+    Pocket,
+}
+
+// the bounding box grown by one, stored in compressed coordinates so that
+// sparse droplets with huge coordinates only need a cell per distinct value
+struct Grid {
+    axes: [Axis; 3],
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    fn new(points: &[Point]) -> Self {
+        let axes = [
+            Axis::new(points.iter().map(|p| p.x)),
+            Axis::new(points.iter().map(|p| p.y)),
+            Axis::new(points.iter().map(|p| p.z)),
+        ];
+        let size = axes.iter().map(Axis::len).product();
+        let mut grid = Grid { axes, cells: vec![Cell::Pocket; size] };
+
+        for &p in points {
+            let index = grid.offset(grid.compress(p)).unwrap();
+            grid.cells[index] = Cell::Lava;
+        }
+
+        // whatever air the outside cannot reach is part of a pocket
+        let start = Point::default();
+        grid.cells[0] = Cell::Exterior;
+        let mut queue = VecDeque::from([start]);
+        while let Some(p) = queue.pop_front() {
+            for n in p.neighbours() {
+                if let Some(index) = grid.offset(n) {
+                    if grid.cells[index] == Cell::Pocket {
+                        grid.cells[index] = Cell::Exterior;
+                        queue.push_back(n);
+                    }
                 }
             }
         }
+
+        grid
     }
 
-    let mut queue = VecDeque::new();
-    queue.push_back(Point { x: min_x - 1, y: min_y, z: min_z });
+    fn compress(&self, p: Point) -> Point {
+        let [x, y, z] = &self.axes;
+        Point { x: x.index(p.x), y: y.index(p.y), z: z.index(p.z) }
+    }
+
+    fn offset(&self, p: Point) -> Option<usize> {
+        let [x, y, z] = [p.x, p.y, p.z].map(usize::try_from);
+        let (Ok(x), Ok(y), Ok(z)) = (x, y, z) else { return None };
+        let [len_x, len_y, len_z] = [0, 1, 2].map(|a| self.axes[a].len());
+        if x >= len_x || y >= len_y || z >= len_z {
+            return None;
+        }
 
-    while let Some(p) = queue.pop_front() {
-        seen.insert(p, true);
-        p.neighbours().iter().for_each(|n| {
-            if !seen.get(n).unwrap_or(&true) && !queue.contains(n) {
-                queue.push_back(*n);
+        Some((x * len_y + y) * len_z + z)
+    }
+
+    fn get(&self, p: Point) -> Option<Cell> {
+        self.offset(p).map(|index| self.cells[index])
+    }
+
+    // the cell of the grid a real cube falls in, the neighbours of a lava cube
+    // are always inside the grid thanks to the padding
+    fn cell_of(&self, p: Point) -> Cell {
+        self.get(self.compress(p)).unwrap()
+    }
+
+    fn expand(&self, p: Point) -> Region {
+        let [x, y, z] = &self.axes;
+        let ((min_x, max_x), (min_y, max_y), (min_z, max_z)) = (x.span(p.x), y.span(p.y), z.span(p.z));
+
+        Region {
+            min: Point { x: min_x, y: min_y, z: min_z },
+            max: Point { x: max_x, y: max_y, z: max_z },
+        }
+    }
+
+    // pockets as groups of regions of real coordinates, with their surfaces
+    fn pockets(&self) -> Vec<(Vec<Region>, usize)> {
+        let mut air = HashSet::new();
+        for x in 0..self.axes[0].len() as isize {
+            for y in 0..self.axes[1].len() as isize {
+                for z in 0..self.axes[2].len() as isize {
+                    let p = Point { x, y, z };
+                    if self.get(p) == Some(Cell::Pocket) {
+                        air.insert(p);
+                    }
+                }
             }
-        })
+        }
+
+        // a pocket cell next to lava has the lava cube's unit width along the
+        // two other axes, so each such pair is exactly one face
+        connected_components(&air)
+            .into_iter()
+            .map(|cells| {
+                let surface = cells
+                    .iter()
+                    .flat_map(|p| p.neighbours())
+                    .filter(|&n| self.get(n) == Some(Cell::Lava))
+                    .count();
+
+                (cells.into_iter().map(|p| self.expand(p)).collect(), surface)
+            })
+            .collect()
+    }
+}
+
+// an inclusive box of cubes
+#[derive(Clone, Copy)]
+struct Region {
+    min: Point,
+    max: Point,
+}
+
+impl Region {
+    fn volume(&self) -> usize {
+        ((self.max.x - self.min.x + 1) * (self.max.y - self.min.y + 1)
+            * (self.max.z - self.min.z + 1)) as usize
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (min, max) = (self.min, self.max);
+        let spans = [(min.x, max.x), (min.y, max.y), (min.z, max.z)]
+            .map(|(a, b)| if a == b { a.to_string() } else { format!("{}..{}", a, b) });
+
+        write!(f, "{}", spans.join(","))
+    }
+}
+
+// splits cells into groups connected through their faces
+fn connected_components(cells: &HashSet<Point>) -> Vec<Vec<Point>> {
+    let mut seen = HashSet::new();
+    let mut components = Vec::new();
+
+    for &start in cells.iter().sorted() {
+        if !seen.insert(start) {
+            continue;
+        }
+
+        let mut component = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(p) = queue.pop_front() {
+            for n in p.neighbours() {
+                if cells.contains(&n) && seen.insert(n) {
+                    component.push(n);
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        component.sort();
+        components.push(component);
+    }
+
+    components
+}
+
+fn inner_area(grid: &Grid, points: &[Point]) -> usize {
+    let cubes: HashSet<_> = points.iter().collect();
+
+    cubes
+        .iter()
+        .flat_map(|p| p.neighbours())
+        .filter(|&n| grid.cell_of(n) == Cell::Pocket)
+        .count()
+}
+
+struct Component {
+    regions: Vec<Region>,
+    surface: usize,
+}
+
+impl Component {
+    fn volume(&self) -> usize {
+        self.regions.iter().map(Region::volume).sum()
     }
+}
 
-    let inner_points: Vec<_> = seen
-        .into_iter()
-        .filter(|&(_, was_seen)| !was_seen)
-        .map(|(p, _)| p)
-        .collect();
-    surface_area(&inner_points)
+struct Analysis {
+    min: Point,
+    max: Point,
+    lava: Vec<Component>,
+    pockets: Vec<Component>,
+}
+
+impl Analysis {
+    fn new(grid: &Grid, points: &[Point]) -> Self {
+        let (min, max) = bounds(points);
+        let lava = connected_components(&points.iter().copied().collect())
+            .into_iter()
+            .map(|voxels| Component {
+                surface: surface_area(&voxels),
+                regions: voxels.into_iter().map(|p| Region { min: p, max: p }).collect(),
+            })
+            .collect();
+        let pockets = grid
+            .pockets()
+            .into_iter()
+            .map(|(regions, surface)| Component { regions, surface })
+            .collect();
+
+        Analysis { min, max, lava, pockets }
+    }
+
+    fn print(&self, with_voxels: bool) {
+        let Analysis { min, max, .. } = self;
+        println!("Bounding box: {},{},{} to {},{},{}", min.x, min.y, min.z, max.x, max.y, max.z);
+
+        for (kind, components) in [("Lava component", &self.lava), ("Air pocket", &self.pockets)] {
+            println!("{}s: {}", kind, components.len());
+
+            for (i, c) in components.iter().enumerate() {
+                println!("  {} {}: volume {}, surface {}", kind, i + 1, c.volume(), c.surface);
+                if with_voxels {
+                    println!("    {}", c.regions.iter().format(" "));
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Face {
+    cube: Point,
+    // index into Point::neighbours, the side of the cube the face looks at
+    side: usize,
+}
+
+impl Face {
+    fn normal(&self) -> [isize; 3] {
+        let mut normal = [0; 3];
+        normal[self.side / 2] = if self.side % 2 == 1 { 1 } else { -1 };
+
+        normal
+    }
+
+    // corners in counter-clockwise order when looking at the face from outside
+    fn corners(&self) -> [[isize; 3]; 4] {
+        let axis = self.side / 2;
+        let positive = self.side % 2 == 1;
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let origin = [self.cube.x, self.cube.y, self.cube.z];
+
+        let mut corners = [(0, 0), (1, 0), (1, 1), (0, 1)].map(|(du, dv)| {
+            let mut corner = origin;
+            corner[axis] += positive as isize;
+            corner[u] += du;
+            corner[v] += dv;
+            corner
+        });
+
+        if !positive {
+            corners.reverse();
+        }
+
+        corners
+    }
+}
+
+// faces of lava cubes touching the outside air and the ones facing air pockets
+fn faces(grid: &Grid, points: &[Point]) -> (Vec<Face>, Vec<Face>) {
+    let mut outer = Vec::new();
+    let mut pockets = Vec::new();
+
+    for &cube in points.iter().sorted().dedup() {
+        for (side, &n) in cube.neighbours().iter().enumerate() {
+            match grid.cell_of(n) {
+                Cell::Exterior => outer.push(Face { cube, side }),
+                Cell::Pocket => pockets.push(Face { cube, side }),
+                Cell::Lava => (),
+            }
+        }
+    }
+
+    (outer, pockets)
+}
+
+fn write_obj(path: &Path, groups: &[(&str, &[Face])]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut vertices = HashMap::<[isize; 3], usize>::new();
+
+    for &(name, faces) in groups {
+        writeln!(out, "g {}", name)?;
+
+        for face in faces {
+            let mut indexes = [0; 4];
+            for (i, corner) in face.corners().into_iter().enumerate() {
+                // obj indexes start at one
+                let next = vertices.len() + 1;
+                indexes[i] = *vertices.entry(corner).or_insert(next);
+                if indexes[i] == next {
+                    writeln!(out, "v {} {} {}", corner[0], corner[1], corner[2])?;
+                }
+            }
+
+            let [a, b, c, d] = indexes;
+            writeln!(out, "f {} {} {} {}", a, b, c, d)?;
+        }
+    }
+
+    out.flush()
+}
+
+fn write_stl(path: &Path, groups: &[(&str, &[Face])]) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    for &(name, faces) in groups {
+        writeln!(out, "solid {}", name)?;
+
+        for face in faces {
+            let [nx, ny, nz] = face.normal();
+            let [a, b, c, d] = face.corners();
+
+            for triangle in [[a, b, c], [a, c, d]] {
+                writeln!(out, "  facet normal {} {} {}", nx, ny, nz)?;
+                writeln!(out, "    outer loop")?;
+                for [x, y, z] in triangle {
+                    writeln!(out, "      vertex {} {} {}", x, y, z)?;
+                }
+                writeln!(out, "    endloop")?;
+                writeln!(out, "  endfacet")?;
+            }
+        }
+
+        writeln!(out, "endsolid {}", name)?;
+    }
+
+    out.flush()
 }
 
 fn main() {
@@ -123,7 +463,37 @@ fn main() {
     println!("[Part 1] The surface area of the scanned \
               lava droplet is {}", surface);
 
-    let inner = inner_area(&droplets);
+    let grid = Grid::new(&droplets);
+    let inner = inner_area(&grid, &droplets);
     println!("[Part 2] The outer surface area of the scanned \
               lava droplet is {}", surface - inner);
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    if let ["analyse", ref rest @ ..] = args[..] {
+        Analysis::new(&grid, &droplets).print(rest.contains(&"--voxels"));
+    } else if let [format @ ("obj" | "stl"), file, ref rest @ ..] = args[..] {
+        let (outer, pockets) = faces(&grid, &droplets);
+        let with_pockets = rest.contains(&"--pockets");
+
+        let mut groups = vec![("exterior", &outer[..])];
+        if with_pockets {
+            groups.push(("pockets", &pockets[..]));
+        }
+
+        let path = Path::new(file);
+        let written = match format {
+            "obj" => write_obj(path, &groups),
+            _ => write_stl(path, &groups),
+        };
+        if let Err(e) = written {
+            eprintln!("could not write the mesh to {}: {}", path.display(), e);
+            process::exit(1);
+        }
+
+        println!("Written {} exterior faces{} to {}", outer.len(),
+                 if with_pockets { format!(" and {} pocket faces", pockets.len()) }
+                 else { String::new() },
+                 path.display());
+    }
 }
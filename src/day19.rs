@@ -1,255 +1,497 @@
-use std::collections::HashSet;
-use std::fs::File;
-use std::io::prelude::*;
-use std::io::BufReader;
-use std::ops::{Add, Sub};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
 use std::path::Path;
+use std::process;
+use std::sync::atomic::{self, AtomicUsize};
+use std::thread;
+use std::time::{Duration, Instant};
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct Resources {
-    ore: u8,
-    clay: u8,
-    obsidian: u8,
-    geode: u8,
+#[derive(Clone, Debug)]
+struct Recipe {
+    produces: usize,
+    cost: Vec<u64>,
+}
+
+#[derive(Clone, Debug)]
+struct Blueprint {
+    id: usize,
+    kinds: Vec<String>,
+    recipes: Vec<Recipe>,
+    target: usize,
+    // The kind of the one robot available at minute zero, by default what
+    // the first recipe produces (ore in the puzzle), see `--start`.
+    start: usize,
 }
 
-impl Resources {
-    fn has_enough(&self, needed: &Resources) -> bool {
-        self.ore >= needed.ore 
-        && self.clay >= needed.clay
-        && self.obsidian >= needed.obsidian
-        && self.geode >= needed.geode
+fn kind_index(kinds: &mut Vec<String>, name: &str) -> usize {
+    match kinds.iter().position(|k| k == name) {
+        Some(i) => i,
+        None => {
+            kinds.push(name.to_string());
+            kinds.len() - 1
+        },
     }
 }
 
-impl Add for Resources {
-    type Output = Self;
+impl Blueprint {
+    fn parse_all(input: &str) -> Vec<Blueprint> {
+        input
+            .split("Blueprint")
+            .filter(|chunk| !chunk.trim().is_empty())
+            .map(Blueprint::parse)
+            .collect()
+    }
 
-    fn add(self, rhs: Self) -> Self {
-        Resources {
-            ore: self.ore + rhs.ore,
-            clay: self.clay + rhs.clay,
-            obsidian: self.obsidian + rhs.obsidian,
-            geode: self.geode + rhs.geode,
+    fn parse(input: &str) -> Blueprint {
+        let (id, recipes) = input.split_once(':').expect("blueprint id is missing");
+        let id = id.trim().parse().unwrap();
+
+        let mut kinds = Vec::new();
+        let mut parsed = Vec::new();
+
+        for sentence in recipes.split_terminator('.').filter(|s| !s.trim().is_empty()) {
+            let words: Vec<_> = sentence.split_whitespace().collect();
+            let ["Each", produces, "robot", "costs", cost @ ..] = &words[..] else {
+                panic!("unexpected robot recipe: {}", sentence.trim());
+            };
+
+            let produces = kind_index(&mut kinds, produces);
+            let cost: Vec<_> = cost
+                .iter()
+                .filter(|&&w| w != "and")
+                .collect::<Vec<_>>()
+                .chunks(2)
+                .map(|pair| {
+                    let amount = pair[0].parse::<u64>().unwrap();
+                    (kind_index(&mut kinds, pair[1]), amount)
+                })
+                .collect();
+
+            parsed.push((produces, cost));
         }
-    }
-}
 
-impl Sub for Resources {
-    type Output = Self;
+        let recipes: Vec<_> = parsed
+            .into_iter()
+            .map(|(produces, amounts)| {
+                let mut cost = vec![0; kinds.len()];
+                for (kind, amount) in amounts {
+                    cost[kind] += amount;
+                }
+
+                Recipe { produces, cost }
+            })
+            .collect();
+
+        let target = (0..kinds.len())
+            .rev()
+            .find(|&kind| recipes.iter().all(|r| r.cost[kind] == 0))
+            .expect("every resource is spent on robots, nothing to maximize");
+
+        let start = recipes.first().expect("blueprint has no recipes").produces;
 
-    fn sub(self, rhs: Self) -> Self {
-        Resources {
-            ore: self.ore - rhs.ore,
-            clay: self.clay - rhs.clay,
-            obsidian: self.obsidian - rhs.obsidian,
-            geode: self.geode - rhs.geode,
+        Blueprint { id, kinds, recipes, target, start }
+    }
+
+    fn robot_name(&self, kind: usize) -> String {
+        if kind == self.target {
+            format!("{}-cracking", self.kinds[kind])
+        } else {
+            format!("{}-collecting", self.kinds[kind])
         }
     }
-}
 
-#[derive(Clone, Debug)]
-struct Blueprint {
-    ore_robot: Resources,
-    clay_robot: Resources,
-    obsidian_robot: Resources,
-    geode_robot: Resources,
-}
+    fn starting_robots(&self) -> Vec<u64> {
+        let mut robots = vec![0; self.kinds.len()];
+        robots[self.start] = 1;
 
-impl Blueprint {
-    fn parse(input: &str) -> Blueprint {
-        let mut iter = input.split_terminator('.');
+        robots
+    }
+}
 
-        let ore = iter.next().unwrap().split_whitespace().nth(6).unwrap().parse().unwrap();
-        let ore_robot = Resources { ore, ..Default::default() };
+struct Plan {
+    amount: u64,
+    builds: Vec<Option<usize>>,
+}
 
-        let ore = iter.next().unwrap().split_whitespace().nth(4).unwrap().parse().unwrap();
-        let clay_robot = Resources { ore, ..Default::default() };
+impl Plan {
+    fn narrate(&self, blueprint: &Blueprint) -> String {
+        let mut out = String::new();
+        let mut resources = vec![0_u64; blueprint.kinds.len()];
+        let mut robots = blueprint.starting_robots();
+
+        for (minute, &build) in self.builds.iter().enumerate() {
+            out += &format!("== Minute {} ==\n", minute + 1);
+
+            if let Some(recipe) = build {
+                let recipe = &blueprint.recipes[recipe];
+                let spent: Vec<_> = recipe.cost
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &amount)| amount > 0)
+                    .map(|(kind, amount)| format!("{} {}", amount, blueprint.kinds[kind]))
+                    .collect();
+                let name = blueprint.robot_name(recipe.produces);
+                let article = if name.starts_with(['a', 'e', 'i', 'o', 'u']) { "an" } else { "a" };
+
+                out += &format!("Spend {} to start building {} {} robot.\n",
+                                spent.join(" and "), article, name);
+                for (have, spent) in resources.iter_mut().zip(&recipe.cost) {
+                    *have -= spent;
+                }
+            }
 
-        let mut obsidian_robot = iter.next().unwrap().split_whitespace();
-        let ore = obsidian_robot.nth(4).unwrap().parse().unwrap();
-        let clay = obsidian_robot.nth(2).unwrap().parse().unwrap();
-        let obsidian_robot = Resources { ore, clay, ..Default::default() };
+            for (kind, &count) in robots.iter().enumerate() {
+                if count == 0 {
+                    continue;
+                }
 
-        let mut geode_robot = iter.next().unwrap().split_whitespace();
-        let ore = geode_robot.nth(4).unwrap().parse().unwrap();
-        let obsidian = geode_robot.nth(2).unwrap().parse().unwrap();
-        let geode_robot = Resources { ore, obsidian, ..Default::default() };
+                resources[kind] += count;
+                let plural = if count > 1 { "s" } else { "" };
+                let verb_ending = if count > 1 { "" } else { "s" };
+                let total = resources[kind];
+                let resource = &blueprint.kinds[kind];
+                out += &if kind == blueprint.target {
+                    format!("{} {} robot{} crack{} {} {}{}; you now have {} open {}{}.\n",
+                            count, blueprint.robot_name(kind), plural, verb_ending, count,
+                            resource, plural, total, resource, if total > 1 { "s" } else { "" })
+                } else {
+                    format!("{} {} robot{} collect{} {} {}; you now have {} {}.\n",
+                            count, blueprint.robot_name(kind), plural, verb_ending,
+                            count, resource, total, resource)
+                };
+            }
+
+            if let Some(recipe) = build {
+                let kind = blueprint.recipes[recipe].produces;
+                robots[kind] += 1;
+                out += &format!("The new {} robot is ready; you now have {} of them.\n",
+                                blueprint.robot_name(kind), robots[kind]);
+            }
+
+            out.push('\n');
+        }
 
-        Blueprint { ore_robot, clay_robot, obsidian_robot, geode_robot }
+        out
     }
 }
 
-#[derive(Default, Clone, Debug, PartialEq, Eq, Hash)]
-struct Robots {
-    ore: u8,
-    clay: u8,
-    obsidian: u8,
-    geode: u8,
+struct Search<'a> {
+    blueprint: &'a Blueprint,
+    time_limit: u64,
+    max_robots: Vec<u64>,
+    target_cost: Vec<u64>,
+    best: u64,
+    builds: Vec<(u64, usize)>,
+    best_builds: Vec<(u64, usize)>,
+    chain_bounds: HashMap<(usize, u64, u64, u64), u64>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-struct Entry {
-    time_left: u8,
-    resources: Resources,
-    robots: Robots,
-}
+impl Search<'_> {
+    fn wait_time(&self, recipe: &Recipe, resources: &[u64], robots: &[u64]) -> Option<u64> {
+        recipe.cost
+            .iter()
+            .zip(resources)
+            .zip(robots)
+            .map(|((&cost, &have), &count)| {
+                match (cost.saturating_sub(have), count) {
+                    (0, _) => Some(0),
+                    (_, 0) => None,
+                    (missing, count) => Some(missing.div_ceil(count)),
+                }
+            })
+            .try_fold(0, |wait, kind_wait| Some(wait.max(kind_wait?)))
+    }
 
-fn get_max_geode_count(blueprint: Blueprint, time_limit: u8) -> u8 {
-    let mut stack = vec![Entry {
-        time_left: time_limit,
-        resources: Resources { ..Default::default() },
-        robots: Robots { ore: 1, ..Default::default() }
-    }];
-    let mut seen = HashSet::new();
-    let mut result = 0;
-
-    let max_required_ore = [
-        blueprint.ore_robot.ore,
-        blueprint.clay_robot.ore,
-        blueprint.obsidian_robot.ore,
-        blueprint.geode_robot.ore
-    ].into_iter().max().unwrap();
-
-    let max_required_clay = [
-        blueprint.ore_robot.clay,
-        blueprint.clay_robot.clay,
-        blueprint.obsidian_robot.clay,
-        blueprint.geode_robot.clay
-    ].into_iter().max().unwrap();
-
-    let max_required_obsidian = [
-        blueprint.ore_robot.obsidian,
-        blueprint.clay_robot.obsidian,
-        blueprint.obsidian_robot.obsidian,
-        blueprint.geode_robot.obsidian
-    ].into_iter().max().unwrap();
-
-    let arith_prog_sum = |from, to| (to - from) * (from + to) / 2;
-
-    while let Some(entry) = stack.pop() {
-        if !seen.insert(entry.clone()) {
-            continue;
-        }
+    // relaxed game where every other robot kind gets a free robot every minute
+    // until it reaches its cap, and target robots are built as soon as their
+    // cost can be paid
+    fn upper_bound(&self, time_left: u64, resources: &[u64], robots: &[u64]) -> u64 {
+        let target = self.blueprint.target;
+        let mut resources = resources.to_vec();
+        let mut robots = robots.to_vec();
+
+        for _ in 0..time_left {
+            let built = resources.iter().zip(&self.target_cost).all(|(have, cost)| have >= cost);
+            if built {
+                for (have, cost) in resources.iter_mut().zip(&self.target_cost) {
+                    *have -= cost;
+                }
+            }
 
-        let Entry { time_left, resources, robots } = entry;
+            for (have, count) in resources.iter_mut().zip(&robots) {
+                *have += count;
+            }
 
-        let time_left = time_left - 1;
-        if time_left == 0 {
-            result = result.max(resources.geode + robots.geode);
-            continue;
+            for recipe in &self.blueprint.recipes {
+                let kind = recipe.produces;
+                if kind != target && robots[kind] < self.max_robots[kind] {
+                    robots[kind] += 1;
+                }
+            }
+            if built {
+                robots[target] += 1;
+            }
         }
 
-        let theor_max = resources.geode as u16 + arith_prog_sum(
-            robots.geode as u16, (robots.geode + time_left + 1) as u16
-        );
-        if theor_max <= result as u16 {
-            continue;
-        }
+        resources[target]
+    }
 
-        let collected = Resources {
-            ore: robots.ore,
-            clay: robots.clay,
-            obsidian: robots.obsidian,
-            geode: robots.geode,
-        };
+    // the game cut down to a single resource the target robot needs, robots
+    // of that kind come for free but still take the one build of a minute;
+    // returns what target robots built from now on can still produce
+    fn chain_bound(&mut self, kind: usize, time_left: u64, robots: u64, stock: u64) -> u64 {
+        if time_left <= 1 {
+            return 0;
+        }
 
-        if resources.has_enough(&blueprint.geode_robot) {
-            stack.push(Entry {
-                time_left,
-                resources: resources - blueprint.geode_robot + collected,
-                robots: Robots {
-                    geode: robots.geode + 1,
-                    ..robots.clone()
-                }
-            });
-            continue;
+        let cost = self.target_cost[kind];
+        let stock = stock.min(cost * time_left);
+        if let Some(&bound) = self.chain_bounds.get(&(kind, time_left, robots, stock)) {
+            return bound;
         }
 
-        if robots.ore < max_required_ore
-           && resources.has_enough(&blueprint.ore_robot) {
-            stack.push(Entry {
-                time_left,
-                resources: resources - blueprint.ore_robot + collected,
-                robots: Robots {
-                    ore: robots.ore + 1,
-                    ..robots.clone()
-                }
-            });
+        let mut bound = self.chain_bound(kind, time_left - 1,
+                                         (robots + 1).min(self.max_robots[kind]), stock + robots);
+        if stock >= cost {
+            let built = self.chain_bound(kind, time_left - 1, robots, stock - cost + robots);
+            bound = bound.max(time_left - 1 + built);
         }
-        if robots.clay < max_required_clay
-           && resources.has_enough(&blueprint.clay_robot) {
-            stack.push(Entry {
-                time_left,
-                resources: resources - blueprint.clay_robot + collected,
-                robots: Robots {
-                    clay: robots.clay + 1,
-                    ..robots.clone()
-                }
-            });
+
+        self.chain_bounds.insert((kind, time_left, robots, stock), bound);
+
+        bound
+    }
+
+    fn explore(&mut self, time_left: u64, resources: &[u64], robots: &[u64]) {
+        let target = self.blueprint.target;
+        let idle = resources[target] + robots[target] * time_left;
+        if idle > self.best {
+            self.best = idle;
+            self.best_builds = self.builds.clone();
         }
-        if robots.obsidian < max_required_obsidian
-           && resources.has_enough(&blueprint.obsidian_robot) {
-            stack.push(Entry {
-                time_left,
-                resources: resources - blueprint.obsidian_robot + collected,
-                robots: Robots {
-                    obsidian: robots.obsidian + 1,
-                    ..robots.clone()
-                }
-            });
+
+        let chain_kinds: Vec<_> = (0..robots.len())
+            .filter(|&kind| self.target_cost[kind] > 0 && self.target_cost[kind] != u64::MAX)
+            .collect();
+        let chain = chain_kinds
+            .into_iter()
+            .map(|kind| idle + self.chain_bound(kind, time_left, robots[kind], resources[kind]))
+            .min();
+        if chain.is_some_and(|bound| bound <= self.best)
+            || self.upper_bound(time_left, resources, robots) <= self.best {
+            return;
         }
 
-        if robots.ore < max_required_ore
-           || robots.clay < max_required_clay
-           || robots.obsidian < max_required_obsidian {
-            stack.push(Entry {
-                time_left,
-                resources: resources + collected,
-                robots: robots
-            });
+        let blueprint = self.blueprint;
+        for (i, recipe) in blueprint.recipes.iter().enumerate().rev() {
+            let kind = recipe.produces;
+            if robots[kind] >= self.max_robots[kind] {
+                continue;
+            }
+
+            // the stock already covers the most that can be spent every minute
+            if kind != target
+                && resources[kind] + robots[kind] * time_left >= self.max_robots[kind] * time_left {
+                continue;
+            }
+
+            let Some(wait) = self.wait_time(recipe, resources, robots) else {
+                continue;
+            };
+            if wait + 1 >= time_left {
+                continue;
+            }
+
+            let next_resources: Vec<_> = resources
+                .iter()
+                .zip(robots)
+                .zip(&recipe.cost)
+                .map(|((have, count), cost)| have + count * (wait + 1) - cost)
+                .collect();
+            let mut next_robots = robots.to_vec();
+            next_robots[recipe.produces] += 1;
+
+            self.builds.push((self.time_limit - time_left + wait, i));
+            self.explore(time_left - wait - 1, &next_resources, &next_robots);
+            self.builds.pop();
         }
     }
+}
+
+fn find_best_plan(blueprint: &Blueprint, time_limit: u64) -> Plan {
+    let kinds = blueprint.kinds.len();
+
+    let mut max_robots: Vec<_> = (0..kinds)
+        .map(|kind| blueprint.recipes.iter().map(|r| r.cost[kind]).max().unwrap_or(0))
+        .collect();
+    max_robots[blueprint.target] = u64::MAX;
+
+    let target_cost = (0..kinds)
+        .map(|kind| blueprint.recipes
+            .iter()
+            .filter(|r| r.produces == blueprint.target)
+            .map(|r| r.cost[kind])
+            .min()
+            .unwrap_or(u64::MAX))
+        .collect();
+
+    let mut search = Search {
+        blueprint,
+        time_limit,
+        max_robots,
+        target_cost,
+        best: 0,
+        builds: Vec::new(),
+        best_builds: Vec::new(),
+        chain_bounds: HashMap::new(),
+    };
+
+    search.explore(time_limit, &vec![0; kinds], &blueprint.starting_robots());
+
+    let mut builds = vec![None; time_limit as usize];
+    for &(minute, recipe) in &search.best_builds {
+        builds[minute as usize] = Some(recipe);
+    }
+
+    Plan { amount: search.best, builds }
+}
+
+struct Evaluation {
+    plan: Plan,
+    elapsed: Duration,
+}
+
+fn evaluate_all(blueprints: &[Blueprint], time_limit: u64, threads: usize) -> Vec<Evaluation> {
+    let next = AtomicUsize::new(0);
+
+    let mut evaluated: Vec<(usize, Evaluation)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.clamp(1, blueprints.len().max(1)))
+            .map(|_| scope.spawn(|| {
+                let mut done = Vec::new();
+
+                loop {
+                    let i = next.fetch_add(1, atomic::Ordering::Relaxed);
+                    let Some(blueprint) = blueprints.get(i) else {
+                        break;
+                    };
 
-    result
+                    let start = Instant::now();
+                    let plan = find_best_plan(blueprint, time_limit);
+                    done.push((i, Evaluation { plan, elapsed: start.elapsed() }));
+                }
+
+                done
+            }))
+            .collect();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    });
+
+    evaluated.sort_unstable_by_key(|&(i, _)| i);
+
+    evaluated.into_iter().map(|(_, evaluation)| evaluation).collect()
 }
 
-fn part1<'a>(blueprints: impl Iterator<Item = &'a Blueprint>) {
-    const TIME_LIMIT: u8 = 24;
+fn report_times(blueprints: &[Blueprint], evaluations: &[Evaluation], time_limit: u64) {
+    for (blueprint, evaluation) in blueprints.iter().zip(evaluations) {
+        println!("  Blueprint {} yields {} {} in {} minutes, searched in {:.3?}",
+                 blueprint.id, evaluation.plan.amount, blueprint.kinds[blueprint.target],
+                 time_limit, evaluation.elapsed);
+    }
+}
+
+fn part1(blueprints: &[Blueprint], threads: usize) {
+    const TIME_LIMIT: u64 = 24;
 
-    let result: usize = blueprints
-        .enumerate()
-        .map(|(i, bp)| (i + 1) * get_max_geode_count(bp.clone(), TIME_LIMIT) as usize)
+    let evaluations = evaluate_all(blueprints, TIME_LIMIT, threads);
+    let result: usize = evaluations
+        .iter()
+        .zip(blueprints)
+        .map(|(evaluation, blueprint)| blueprint.id * evaluation.plan.amount as usize)
         .sum();
 
     println!("[Part 1] The sum of quality levels of all of the blueprints is {}",
              result);
+    report_times(blueprints, &evaluations, TIME_LIMIT);
 }
 
-fn part2<'a>(blueprints: impl Iterator<Item = &'a Blueprint>) {
-    const TIME_LIMIT: u8 = 32;
+fn part2(blueprints: &[Blueprint], threads: usize) {
+    const TIME_LIMIT: u64 = 32;
 
-    let result: u32 = blueprints
-        .take(3)
-        .map(|bp| get_max_geode_count(bp.clone(), TIME_LIMIT) as u32)
+    let blueprints = &blueprints[..blueprints.len().min(3)];
+    let evaluations = evaluate_all(blueprints, TIME_LIMIT, threads);
+    let result: u32 = evaluations
+        .iter()
+        .map(|evaluation| evaluation.plan.amount as u32)
         .product();
 
     println!("[Part 2] The multiple of the largest number of geodes that could be \
               opened using the first three blueprints is {}", result);
+    report_times(blueprints, &evaluations, TIME_LIMIT);
 }
 
 fn main() {
     let path = Path::new("inputs/day19.txt");
-    let reader = match File::open(path) {
+    let input = match fs::read_to_string(path) {
         Err(e) => panic!("could not open input file at {}: {}", path.display(), e),
-        Ok(file) => BufReader::new(file),
+        Ok(input) => input,
     };
 
-    let blueprints: Vec<_> = reader
-        .lines()
-        .map(|l| Blueprint::parse(&l.unwrap()))
-        .collect();
+    let mut blueprints = Blueprint::parse_all(&input);
+
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if let Some(i) = args.iter().position(|a| a == "--start") {
+        let Some(name) = args.get(i + 1).cloned() else {
+            eprintln!("--start expects a resource name");
+            process::exit(1);
+        };
+
+        for blueprint in &mut blueprints {
+            let Some(kind) = blueprint.kinds.iter().position(|k| *k == name) else {
+                eprintln!("Blueprint {} has no resource called '{}'", blueprint.id, name);
+                process::exit(1);
+            };
+            blueprint.start = kind;
+        }
+        args.drain(i..i + 2);
+    }
+
+    let threads = match args.iter().position(|a| a == "--threads") {
+        Some(i) => {
+            let threads = args
+                .get(i + 1)
+                .and_then(|n| n.parse().ok())
+                .filter(|&n: &usize| n > 0)
+                .unwrap_or_else(|| {
+                    eprintln!("--threads expects a positive number");
+                    process::exit(1);
+                });
+            args.drain(i..i + 2);
+            threads
+        },
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    if args.first().map(String::as_str) == Some("plan") {
+        let number = args.get(1).and_then(|n| n.parse::<usize>().ok());
+        let time_limit = args.get(2).and_then(|n| n.parse::<u64>().ok()).unwrap_or(24);
+
+        let Some(blueprint) = blueprints.iter().find(|b| Some(b.id) == number) else {
+            eprintln!("usage: day19 plan <blueprint number> [minutes]");
+            process::exit(1);
+        };
+
+        let plan = find_best_plan(blueprint, time_limit);
+        print!("{}", plan.narrate(blueprint));
+        println!("Blueprint {} yields {} {} in {} minutes", blueprint.id,
+                 plan.amount, blueprint.kinds[blueprint.target], time_limit);
+        return;
+    }
 
-    part1(blueprints.iter());
-    part2(blueprints.iter());
+    part1(&blueprints, threads);
+    part2(&blueprints, threads);
 }
@@ -1,15 +1,59 @@
-use std::collections::{HashMap, VecDeque};
+mod arith;
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
-use std::ops::{Add, Sub, Mul, Div};
 use std::path::Path;
+use std::process;
+
+use arith::{ArithError, Backend, BigInt, Integer};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn parse(input: &str) -> Self {
+        match input {
+            "+" => Op::Add,
+            "-" => Op::Sub,
+            "*" => Op::Mul,
+            "/" => Op::Div,
+            _ => panic!("unexpected operation found: {}", input),
+        }
+    }
+
+    fn arith(self) -> arith::Op {
+        match self {
+            Op::Add => arith::Op::Add,
+            Op::Sub => arith::Op::Sub,
+            Op::Mul => arith::Op::Mul,
+            Op::Div => arith::Op::Div,
+        }
+    }
 
-type OpFunc = fn(i64, i64) -> i64;
+    fn symbol(self) -> char {
+        self.arith().symbol()
+    }
+
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
+        }
+    }
+}
 
 enum Job {
     Number(i64),
-    Operation(String, String, OpFunc)
+    Operation(String, String, Op)
 }
 
 impl Job {
@@ -24,129 +68,399 @@ impl Job {
         let job = if let Ok(val) = maybe_num {
             Job::Number(val)
         } else {
-            let operand = iter.next().unwrap();
+            let op = Op::parse(iter.next().unwrap());
             let left = left.to_string();
             let right = iter.next().unwrap().to_string();
 
-            let func = match operand {
-                "+" => Add::add,
-                "-" => Sub::sub,
-                "*" => Mul::mul,
-                "/" => Div::div,
-                _ => panic!("unexpected operation found"),
-            };
-
-            Job::Operation(left, right, func)
+            Job::Operation(left, right, op)
         };
 
         (name, job)
     }
 }
 
-fn get_opposite_operand(op: OpFunc) -> OpFunc {
-    if op == Add::add {
-        Sub::sub
-    } else if op == Sub::sub {
-        Add::add
-    } else if op == Mul::mul {
-        Div::div
-    } else if op == Div::div {
-        Mul::mul
-    } else {
-        panic!("unexpected operation given");
-    }
+struct EvalError {
+    monkey: String,
+    error: ArithError,
 }
 
-fn lookup(name: &String, cache: &mut HashMap<String, i64>, jobs: &HashMap<String, Job>) -> i64 {
-    if let Some(&value) = cache.get(name) {
-        return value;
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "monkey '{}' cannot yell its number: {}", self.monkey, self.error)
     }
+}
 
-    let op = &jobs[name];
+fn lookup<T: Integer>(name: &str, cache: &mut HashMap<String, T>,
+                      jobs: &HashMap<String, Job>) -> Result<T, EvalError> {
+    if let Some(value) = cache.get(name) {
+        return Ok(value.clone());
+    }
 
-    match op {
-        &Job::Number(value) => {
-            cache.insert(name.to_owned(), value);
+    let result = match jobs[name] {
+        Job::Number(value) => T::from_i64(value),
+        Job::Operation(ref left, ref right, op) => {
+            let left = lookup(left, cache, jobs)?;
+            let right = lookup(right, cache, jobs)?;
 
-            value
+            arith::apply(op.arith(), &left, &right).map_err(|error| EvalError {
+                monkey: name.to_owned(),
+                error,
+            })?
         },
-        &Job::Operation(ref left, ref right, operand) => {
-            let left = lookup(left, cache, jobs);
-            let right = lookup(right, cache, jobs);
-            let result = operand(left, right);
-            cache.insert(name.to_owned(), result);
+    };
 
-            result
-        },
-    }
+    cache.insert(name.to_owned(), result.clone());
+
+    Ok(result)
 }
 
-fn calculate(name: &String, jobs: &HashMap<String, Job>) -> i64 {
-    let mut cache = HashMap::<String, i64>::new();
+fn calculate<T: Integer>(name: &str, jobs: &HashMap<String, Job>) -> Result<T, EvalError> {
+    let mut cache = HashMap::<String, T>::new();
 
     lookup(name, &mut cache, jobs)
 }
 
-fn part1(jobs: &HashMap<String, Job>) {
-    let result = calculate(&"root".to_string(), jobs);
-    println!("[Part 1] The monkey named 'root' will yell a number {}", result);
+enum GraphError {
+    Undefined { name: String, used_by: Option<String> },
+    Cycle(Vec<String>),
 }
 
-fn get_left_expected(job: &Job, result: i64, jobs: &HashMap<String, Job>) -> i64 {
-    let &Job::Operation(_, ref right, op) = job else {
-        panic!("unexpected job type given");
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GraphError::Undefined { name, used_by: Some(parent) } =>
+                write!(f, "monkey '{}' waits for an undefined monkey '{}'", parent, name),
+            GraphError::Undefined { name, used_by: None } =>
+                write!(f, "monkey '{}' is not defined", name),
+            GraphError::Cycle(names) =>
+                write!(f, "monkeys wait for each other in a cycle: {}", names.join(" -> ")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+fn visit<'a>(name: &'a str, parent: Option<&'a str>, jobs: &'a HashMap<String, Job>,
+             state: &mut HashMap<&'a str, Visit>, path: &mut Vec<&'a str>,
+             order: &mut Vec<&'a str>) -> Result<(), GraphError> {
+    match state.get(name) {
+        Some(Visit::Done) => return Ok(()),
+        Some(Visit::InProgress) => {
+            let start = path.iter().position(|&n| n == name).unwrap();
+            let mut cycle: Vec<_> = path[start..].iter().map(|n| n.to_string()).collect();
+            cycle.push(name.to_string());
+
+            return Err(GraphError::Cycle(cycle));
+        },
+        None => {},
+    }
+
+    let Some(job) = jobs.get(name) else {
+        return Err(GraphError::Undefined {
+            name: name.to_string(),
+            used_by: parent.map(str::to_string),
+        });
     };
 
-    let right = calculate(right, jobs);
-    let op = get_opposite_operand(op);
+    state.insert(name, Visit::InProgress);
+    path.push(name);
+
+    if let Job::Operation(left, right, _) = job {
+        visit(left, Some(name), jobs, state, path, order)?;
+        visit(right, Some(name), jobs, state, path, order)?;
+    }
+
+    path.pop();
+    state.insert(name, Visit::Done);
+    order.push(name);
 
-    op(result, right)
+    Ok(())
 }
 
-fn get_right_expected(job: &Job, result: i64, jobs: &HashMap<String, Job>) -> i64 {
-    let &Job::Operation(ref left, _, op) = job else {
-        panic!("unexpected job type given");
-    };
+fn evaluation_order<'a>(name: &'a str, jobs: &'a HashMap<String, Job>)
+                        -> Result<Vec<&'a str>, GraphError> {
+    let mut state = HashMap::new();
+    let mut order = Vec::new();
 
-    let left = calculate(left, jobs);
+    visit(name, None, jobs, &mut state, &mut Vec::new(), &mut order)?;
 
-    let (op, l, r) = if op == Add::add || op == Mul::mul {
-        (get_opposite_operand(op), result, left)
-    } else {
-        (op, left, result)
-    };
+    Ok(order)
+}
+
+fn to_infix(name: &str, unknown: Option<&str>, jobs: &HashMap<String, Job>) -> String {
+    fn write_node(name: &str, unknown: Option<&str>, jobs: &HashMap<String, Job>,
+                  out: &mut String) -> Option<Op> {
+        if unknown == Some(name) {
+            out.push_str(name);
+            return None;
+        }
+
+        match jobs[name] {
+            Job::Number(value) => {
+                out.push_str(&value.to_string());
+                None
+            },
+            Job::Operation(ref left, ref right, op) => {
+                write_operand(left, op, false, unknown, jobs, out);
+                out.push(' ');
+                out.push(op.symbol());
+                out.push(' ');
+                write_operand(right, op, true, unknown, jobs, out);
+                Some(op)
+            },
+        }
+    }
+
+    fn write_operand(name: &str, parent: Op, is_right: bool, unknown: Option<&str>,
+                     jobs: &HashMap<String, Job>, out: &mut String) {
+        let mut operand = String::new();
+        let child = write_node(name, unknown, jobs, &mut operand);
+
+        // division truncates, so only a sum or product of the same kind regroups freely
+        let needs_parens = child.is_some_and(|child| {
+            child.precedence() < parent.precedence()
+                || (is_right && child.precedence() == parent.precedence()
+                    && !matches!((parent, child), (Op::Add, Op::Add) | (Op::Mul, Op::Mul)))
+        });
+
+        if needs_parens {
+            out.push('(');
+            out.push_str(&operand);
+            out.push(')');
+        } else {
+            out.push_str(&operand);
+        }
+    }
 
-    op(l, r)
+    let mut out = String::new();
+    write_node(name, unknown, jobs, &mut out);
+
+    out
 }
 
-fn part2(jobs: &HashMap<String, Job>) {
-    let mut queue = VecDeque::<(&String, i64)>::new();
+fn to_dot(order: &[&str], jobs: &HashMap<String, Job>) -> String {
+    let mut out = String::from("digraph monkeys {\n");
+
+    for &name in order {
+        match jobs[name] {
+            Job::Number(value) => {
+                out += &format!("    {} [label=\"{}\\n{}\", shape=box];\n", name, name, value);
+            },
+            Job::Operation(ref left, ref right, op) => {
+                out += &format!("    {} [label=\"{}\\n{}\"];\n", name, name, op.symbol());
+                out += &format!("    {} -> {} [label=\"l\"];\n", name, left);
+                out += &format!("    {} -> {} [label=\"r\"];\n", name, right);
+            },
+        }
+    }
 
-    let &Job::Operation(ref left, ref right, _) = &jobs[&"root".to_string()] else {
-        panic!("unexpected job was given for 'root'");
-    };
+    out.push_str("}\n");
 
-    queue.push_back((right, calculate(left, jobs)));
-    queue.push_back((left, calculate(right, jobs)));
+    out
+}
 
-    let mut result = 0;
-    while let Some((name, expected)) = queue.pop_front() {
-        if name == &"humn".to_string() {
-            result = expected;
-            break;
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+
+    a.abs()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SolveError {
+    NonLinear,
+    DivisionByZero,
+    Overflow,
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::NonLinear => write!(f, "the unknown ends up in a divisor or a product \
+                                              with itself, so the equation is not linear"),
+            SolveError::DivisionByZero => write!(f, "a monkey divides by zero"),
+            SolveError::Overflow => write!(f, "a fraction overflows i128"),
         }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Rational {
+    num: i128,
+    den: i128,
+}
 
-        let job = &jobs[name];
-        if let &Job::Operation(ref left, ref right, op) = job {
-            queue.push_back((left, get_left_expected(job, expected, jobs)));
+impl Rational {
+    const ZERO: Rational = Rational { num: 0, den: 1 };
+    const ONE: Rational = Rational { num: 1, den: 1 };
 
-            if !(expected == 0 && op == Div::div) {
-                queue.push_back((right, get_right_expected(job, expected, jobs)));
-            }
+    fn new(num: i128, den: i128) -> Result<Self, SolveError> {
+        if den == 0 {
+            return Err(SolveError::DivisionByZero);
+        }
+
+        let divisor = gcd(num, den) * den.signum();
+
+        Ok(Rational { num: num / divisor, den: den / divisor })
+    }
+
+    fn is_zero(&self) -> bool {
+        self.num == 0
+    }
+
+    fn as_integer(&self) -> Option<i128> {
+        (self.den == 1).then_some(self.num)
+    }
+
+    fn apply(self, op: Op, other: Self) -> Result<Self, SolveError> {
+        let mul = |a: i128, b: i128| a.checked_mul(b).ok_or(SolveError::Overflow);
+        let (a, b, c, d) = (self.num, self.den, other.num, other.den);
+
+        match op {
+            Op::Add => Rational::new(mul(a, d)?.checked_add(mul(c, b)?)
+                                         .ok_or(SolveError::Overflow)?, mul(b, d)?),
+            Op::Sub => Rational::new(mul(a, d)?.checked_sub(mul(c, b)?)
+                                         .ok_or(SolveError::Overflow)?, mul(b, d)?),
+            Op::Mul => Rational::new(mul(a, c)?, mul(b, d)?),
+            Op::Div => Rational::new(mul(a, d)?, mul(b, c)?),
+        }
+    }
+
+    fn scale(self, linear: Linear) -> Result<Linear, SolveError> {
+        Ok(Linear {
+            coef: self.apply(Op::Mul, linear.coef)?,
+            constant: self.apply(Op::Mul, linear.constant)?,
+        })
+    }
+}
+
+impl From<i64> for Rational {
+    fn from(value: i64) -> Self {
+        Rational { num: value as i128, den: 1 }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
         }
     }
+}
+
+enum Expr {
+    Const(Rational),
+    Var,
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn build(name: &str, unknown: &str, jobs: &HashMap<String, Job>) -> Result<Self, SolveError> {
+        if name == unknown {
+            return Ok(Expr::Var);
+        }
+
+        Ok(match jobs[name] {
+            Job::Number(value) => Expr::Const(value.into()),
+            Job::Operation(ref left, ref right, op) => {
+                let left = Expr::build(left, unknown, jobs)?;
+                let right = Expr::build(right, unknown, jobs)?;
+
+                match (left, right) {
+                    (Expr::Const(l), Expr::Const(r)) => Expr::Const(l.apply(op, r)?),
+                    (l, r) => Expr::Binary(op, Box::new(l), Box::new(r)),
+                }
+            },
+        })
+    }
+
+    fn to_linear(&self) -> Result<Linear, SolveError> {
+        match self {
+            &Expr::Const(value) => Ok(Linear { coef: Rational::ZERO, constant: value }),
+            Expr::Var => Ok(Linear { coef: Rational::ONE, constant: Rational::ZERO }),
+            Expr::Binary(op, left, right) => {
+                let (l, r) = (left.to_linear()?, right.to_linear()?);
+
+                match op {
+                    Op::Add | Op::Sub => Ok(Linear {
+                        coef: l.coef.apply(*op, r.coef)?,
+                        constant: l.constant.apply(*op, r.constant)?,
+                    }),
+                    Op::Mul if l.coef.is_zero() => l.constant.scale(r),
+                    Op::Mul if r.coef.is_zero() => r.constant.scale(l),
+                    Op::Div if r.coef.is_zero() => Rational::ONE.apply(Op::Div, r.constant)?.scale(l),
+                    _ => Err(SolveError::NonLinear),
+                }
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Linear {
+    coef: Rational,
+    constant: Rational,
+}
+
+enum Solution {
+    Unique(Rational),
+    Infinite,
+    Nothing,
+}
+
+fn solve_equality(left: Linear, right: Linear) -> Result<Solution, SolveError> {
+    let coef = left.coef.apply(Op::Sub, right.coef)?;
+    let constant = right.constant.apply(Op::Sub, left.constant)?;
+
+    Ok(if !coef.is_zero() {
+        Solution::Unique(constant.apply(Op::Div, coef)?)
+    } else if constant.is_zero() {
+        Solution::Infinite
+    } else {
+        Solution::Nothing
+    })
+}
+
+fn solve_root(unknown: &str, jobs: &HashMap<String, Job>) -> Result<Solution, SolveError> {
+    let Job::Operation(ref left, ref right, _) = jobs["root"] else {
+        panic!("unexpected job was given for 'root'");
+    };
+
+    let left = Expr::build(left, unknown, jobs)?.to_linear()?;
+    let right = Expr::build(right, unknown, jobs)?.to_linear()?;
+
+    solve_equality(left, right)
+}
+
+fn part1<T: Integer>(jobs: &HashMap<String, Job>) {
+    match calculate::<T>("root", jobs) {
+        Ok(result) => println!("[Part 1] The monkey named 'root' will yell a number {}",
+                               result),
+        Err(e) => println!("[Part 1] Evaluation with {} arithmetic failed, {}", T::NAME, e),
+    }
+}
 
-    println!("[Part 2] The number to yell to pass 'root's equality test is {}", result);
+fn part2(unknown: &str, jobs: &HashMap<String, Job>) {
+    match solve_root(unknown, jobs) {
+        Ok(Solution::Unique(value)) => match value.as_integer() {
+            Some(result) => println!("[Part 2] The number '{}' has to yell to pass 'root's \
+                                      equality test is {}", unknown, result),
+            None => println!("[Part 2] Only a fractional number {} for '{}' passes 'root's \
+                              equality test", value, unknown),
+        },
+        Ok(Solution::Infinite) => println!("[Part 2] Any number '{}' yells passes 'root's \
+                                            equality test", unknown),
+        Ok(Solution::Nothing) => println!("[Part 2] No number '{}' yells passes 'root's \
+                                           equality test", unknown),
+        Err(e) => println!("[Part 2] Solving for '{}' failed, {}", unknown, e),
+    }
 }
 
 fn main() {
@@ -161,6 +475,102 @@ fn main() {
         .map(|l| Job::parse(&l.unwrap()))
         .collect();
 
-    part1(&jobs);
-    part2(&jobs);
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    let mut backend = Backend::I64;
+    if let Some(i) = args.iter().position(|a| a == "--arith") {
+        let name = args.get(i + 1).map_or("", String::as_str);
+        backend = Backend::parse(name).unwrap_or_else(|| {
+            eprintln!("unknown arithmetic '{}', expected one of: i64, i128, big", name);
+            process::exit(1);
+        });
+        args.drain(i..(i + 2).min(args.len()));
+    }
+
+    let mut unknown = String::from("humn");
+    if let Some(i) = args.iter().position(|a| a == "--unknown") {
+        let Some(name) = args.get(i + 1) else {
+            eprintln!("--unknown expects the name of a monkey");
+            process::exit(1);
+        };
+        if !jobs.contains_key(name) {
+            eprintln!("monkey '{}' is not defined", name);
+            process::exit(1);
+        }
+        unknown = name.clone();
+        args.drain(i..i + 2);
+    }
+
+    let command = args.first().map(String::as_str);
+    let monkey = args.get(1).map_or("root", String::as_str);
+
+    let order = match evaluation_order(monkey, &jobs) {
+        Ok(order) => order,
+        Err(e) => {
+            eprintln!("could not evaluate monkey '{}': {}", monkey, e);
+            process::exit(1);
+        },
+    };
+
+    match command {
+        None => {
+            match backend {
+                Backend::I64 => part1::<i64>(&jobs),
+                Backend::I128 => part1::<i128>(&jobs),
+                Backend::Big => part1::<BigInt>(&jobs),
+            }
+            part2(&unknown, &jobs);
+        },
+        Some("infix") => println!("{}", to_infix(monkey, args.get(2).map(String::as_str), &jobs)),
+        Some("dot") => print!("{}", to_dot(&order, &jobs)),
+        Some("order") => println!("{}", order.join(" ")),
+        Some(other) => {
+            eprintln!("unknown command '{}', expected one of: infix, dot, order", other);
+            process::exit(1);
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_jobs(input: &str) -> HashMap<String, Job> {
+        input.lines().map(|l| Job::parse(l.trim())).collect()
+    }
+
+    #[test]
+    fn infix_keeps_truncating_division_grouped() {
+        let jobs = parse_jobs("root: aaaa * bbbb
+                               aaaa: 3
+                               bbbb: cccc / dddd
+                               cccc: 5
+                               dddd: 2");
+
+        assert_eq!(to_infix("root", None, &jobs), "3 * (5 / 2)");
+        assert_eq!(calculate::<i64>("root", &jobs).ok(), Some(6));
+    }
+
+    #[test]
+    fn infix_parentheses() {
+        for (root, infix) in [("aaaa + bbbb", "1 + 8 * 2"), ("aaaa - bbbb", "1 - 8 * 2"),
+                              ("bbbb + cccc", "8 * 2 + (4 - 3)"), ("aaaa - cccc", "1 - (4 - 3)"),
+                              ("bbbb * dddd", "8 * 2 * 6 * 7"), ("bbbb / dddd", "8 * 2 / (6 * 7)"),
+                              ("dddd - eeee", "6 * 7 - 7 / 3"), ("eeee * aaaa", "7 / 3 * 1"),
+                              ("cccc * aaaa", "(4 - 3) * 1")] {
+            let jobs = parse_jobs(&format!("root: {}
+                                            aaaa: 1
+                                            bbbb: ffff * gggg
+                                            cccc: hhhh - iiii
+                                            dddd: jjjj * kkkk
+                                            eeee: kkkk / iiii
+                                            ffff: 8
+                                            gggg: 2
+                                            hhhh: 4
+                                            iiii: 3
+                                            jjjj: 6
+                                            kkkk: 7", root));
+            assert_eq!(to_infix("root", None, &jobs), infix, "root: {}", root);
+        }
+    }
 }
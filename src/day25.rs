@@ -1,69 +1,480 @@
+use std::cmp::Ordering;
+use std::env;
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
+use std::iter::Sum;
+use std::ops::{Add, Mul, Neg, Sub};
 use std::path::Path;
+use std::process;
+use std::str::FromStr;
 
-fn parse_snafu_char(symbol: u8) -> i8 {
-    match symbol {
-        b'2' => 2,
-        b'1' => 1,
-        b'0' => 0,
-        b'-' => -1,
-        b'=' => -2,
-        _ => panic!("unexpected symbol given"),
+struct BalancedBase {
+    name: &'static str,
+    // symbols of the digits, from the most negative one to the most positive one
+    alphabet: &'static [u8],
+}
+
+const TERNARY: BalancedBase = BalancedBase { name: "ternary", alphabet: b"-01" };
+const SNAFU: BalancedBase = BalancedBase { name: "snafu", alphabet: b"=-012" };
+const SEPTENARY: BalancedBase = BalancedBase { name: "septenary", alphabet: b"#=-0123" };
+
+const BASES: [BalancedBase; 3] = [TERNARY, SNAFU, SEPTENARY];
+
+#[derive(Debug, PartialEq, Eq)]
+enum ParseBalancedError {
+    Empty { base: &'static str },
+    Symbol { base: &'static str, symbol: char },
+}
+
+impl fmt::Display for ParseBalancedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseBalancedError::Empty { base } => write!(f, "an empty {} number", base),
+            ParseBalancedError::Symbol { base, symbol } =>
+                write!(f, "unexpected symbol '{}' in a {} number", symbol, base),
+        }
     }
 }
 
-fn from_snafu(input: &str) -> u64 {
-    input
-        .bytes()
-        .rev()
-        .enumerate()
-        .fold(0_i64, |acc, (i, b)|
-            acc + parse_snafu_char(b) as i64 * 5_i64.pow(i as u32)
-        ) as u64
+impl BalancedBase {
+    fn find(name: &str) -> Option<&'static BalancedBase> {
+        BASES.iter().find(|b| b.name == name)
+    }
+
+    fn radix(&self) -> i128 {
+        self.alphabet.len() as i128
+    }
+
+    fn max_digit(&self) -> i8 {
+        (self.alphabet.len() / 2) as i8
+    }
+
+    fn parse_digit(&self, symbol: u8) -> Option<i8> {
+        self.alphabet
+            .iter()
+            .position(|&s| s == symbol)
+            .map(|pos| pos as i8 - self.max_digit())
+    }
+
+    fn digit_symbol(&self, digit: i8) -> char {
+        self.alphabet[(digit + self.max_digit()) as usize] as char
+    }
+
+    fn normalize(&self, mut sums: Vec<i128>) -> Vec<i8> {
+        let max_digit = self.max_digit() as i128;
+        let mut digits = Vec::with_capacity(sums.len() + 1);
+        let mut carry = 0;
+        let mut i = 0;
+
+        while i < sums.len() || carry != 0 {
+            let value = sums.get_mut(i).map_or(0, std::mem::take) + carry;
+            carry = (value + max_digit).div_euclid(self.radix());
+            digits.push((value - carry * self.radix()) as i8);
+            i += 1;
+        }
+
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+
+        digits
+    }
+
+    fn parse(&self, input: &str) -> Result<Vec<i8>, ParseBalancedError> {
+        if input.is_empty() {
+            return Err(ParseBalancedError::Empty { base: self.name });
+        }
+
+        let digits = input
+            .bytes()
+            .rev()
+            .map(|b| self.parse_digit(b).ok_or(ParseBalancedError::Symbol {
+                base: self.name,
+                symbol: b as char,
+            }))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(self.normalize(digits.into_iter().map(i128::from).collect()))
+    }
+
+    fn format(&self, digits: &[i8]) -> String {
+        if digits.is_empty() {
+            return self.digit_symbol(0).to_string();
+        }
+
+        digits.iter().rev().map(|&d| self.digit_symbol(d)).collect()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Snafu {
+    // least significant digit first, no leading zeros, zero has no digits
+    digits: Vec<i8>,
 }
 
-fn to_snafu(mut val: u64) -> String {
-    if val == 0 {
-        return "0".to_string()
+impl Snafu {
+    fn normalize(sums: Vec<i128>) -> Self {
+        Snafu { digits: SNAFU.normalize(sums) }
     }
 
-    let mut s = vec![];
+    fn signum(&self) -> i8 {
+        self.digits.last().map_or(0, |d| d.signum())
+    }
+}
 
-    while val != 0 {
-        let digit = val % 5;
+impl From<i64> for Snafu {
+    fn from(value: i64) -> Self {
+        Snafu::normalize(vec![value as i128])
+    }
+}
+
+impl FromStr for Snafu {
+    type Err = ParseBalancedError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Ok(Snafu { digits: SNAFU.parse(input)? })
+    }
+}
+
+impl fmt::Display for Snafu {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", SNAFU.format(&self.digits))
+    }
+}
+
+impl Neg for Snafu {
+    type Output = Snafu;
+
+    fn neg(self) -> Snafu {
+        Snafu { digits: self.digits.into_iter().map(|d| -d).collect() }
+    }
+}
+
+impl Add for Snafu {
+    type Output = Snafu;
+
+    fn add(self, other: Snafu) -> Snafu {
+        let len = self.digits.len().max(other.digits.len());
+        let sums = (0..len)
+            .map(|i| {
+                *self.digits.get(i).unwrap_or(&0) as i128
+                    + *other.digits.get(i).unwrap_or(&0) as i128
+            })
+            .collect();
+
+        Snafu::normalize(sums)
+    }
+}
+
+impl Sub for Snafu {
+    type Output = Snafu;
+
+    fn sub(self, other: Snafu) -> Snafu {
+        self + -other
+    }
+}
+
+impl Mul for Snafu {
+    type Output = Snafu;
+
+    fn mul(self, other: Snafu) -> Snafu {
+        if self.digits.is_empty() || other.digits.is_empty() {
+            return Snafu::default();
+        }
+
+        let mut sums = vec![0_i128; self.digits.len() + other.digits.len()];
+        for (i, &a) in self.digits.iter().enumerate() {
+            for (j, &b) in other.digits.iter().enumerate() {
+                sums[i + j] += a as i128 * b as i128;
+            }
+        }
 
-        s.push(match digit {
-            0 => b'0',
-            1 => b'1',
-            2 => b'2',
-            3 => b'=',
-            4 => b'-',
-            _ => panic!("wrong digit given")
-        });
+        Snafu::normalize(sums)
+    }
+}
+
+impl Ord for Snafu {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // a difference in a higher digit always outweighs all the lower digits
+        let len = self.digits.len().max(other.digits.len());
+
+        (0..len)
+            .rev()
+            .map(|i| self.digits.get(i).unwrap_or(&0).cmp(other.digits.get(i).unwrap_or(&0)))
+            .find(|&o| o != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    }
+}
 
-        val = (val + 2) / 5;
+impl PartialOrd for Snafu {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    String::from_utf8(
-        s.into_iter().rev().collect::<Vec<_>>()
-    ).unwrap()
+impl Sum for Snafu {
+    fn sum<I: Iterator<Item = Snafu>>(iter: I) -> Snafu {
+        iter.fold(Snafu::default(), Add::add)
+    }
+}
+
+// digits of a decimal number, least significant first, each carrying the sign
+fn parse_decimal(input: &str) -> Result<Vec<i8>, String> {
+    let (sign, body) = match input.strip_prefix('-') {
+        Some(body) => (-1, body),
+        None => (1, input),
+    };
+
+    if body.is_empty() {
+        return Err("an empty decimal number".to_string());
+    }
+
+    body.bytes()
+        .rev()
+        .map(|b| match b {
+            b'0'..=b'9' => Ok(sign * (b - b'0') as i8),
+            _ => Err(format!("unexpected symbol '{}' in a decimal number", b as char)),
+        })
+        .collect()
+}
+
+fn normalize_decimal(mut sums: Vec<i128>) -> Vec<i8> {
+    let mut digits = Vec::with_capacity(sums.len() + 1);
+    let mut carry = 0;
+    let mut i = 0;
+
+    while i < sums.len() || carry != 0 {
+        let value = sums.get_mut(i).map_or(0, std::mem::take) + carry;
+        carry = value.div_euclid(10);
+        digits.push(value.rem_euclid(10) as i8);
+        i += 1;
+    }
+
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+
+    digits
+}
+
+// Horner's rule carried out digit by digit in the target base, so numbers
+// of any length convert without going through a fixed size integer
+fn rebase(digits: &[i8], radix: i128, normalize: impl Fn(Vec<i128>) -> Vec<i8>) -> Vec<i8> {
+    digits.iter().rev().fold(Vec::new(), |acc, &digit| {
+        let mut sums: Vec<i128> = acc.iter().map(|&d| d as i128 * radix).collect();
+        if sums.is_empty() {
+            sums.push(0);
+        }
+        sums[0] += digit as i128;
+
+        normalize(sums)
+    })
+}
+
+fn convert(from: &str, to: &str, number: &str) -> Result<String, String> {
+    let find = |name| BalancedBase::find(name).ok_or_else(|| format!("unknown base '{}'", name));
+
+    let (digits, radix) = match from {
+        "decimal" => (parse_decimal(number)?, 10),
+        name => {
+            let base = find(name)?;
+            (base.parse(number).map_err(|e| e.to_string())?, base.radix())
+        },
+    };
+
+    match to {
+        "decimal" => {
+            // the leading digit carries the sign in both kinds of bases, and
+            // every prefix of a positive number stays positive along the way
+            let negative = digits.iter().rev().find(|&&d| d != 0).is_some_and(|&d| d < 0);
+            let magnitude: Vec<_> = digits.iter().map(|&d| if negative { -d } else { d }).collect();
+            let result = rebase(&magnitude, radix, normalize_decimal);
+
+            let text: String = result.iter().rev().map(|&d| (b'0' + d as u8) as char).collect();
+            Ok(match (negative, text.is_empty()) {
+                (_, true) => "0".to_string(),
+                (true, false) => format!("-{}", text),
+                (false, false) => text,
+            })
+        },
+        name => {
+            let base = find(name)?;
+            Ok(base.format(&rebase(&digits, radix, |sums| base.normalize(sums))))
+        },
+    }
 }
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("convert") {
+        let [from, to, number] = &args[1..] else {
+            eprintln!("usage: day25 convert <from> <to> <number>, where bases are \
+                       decimal, ternary, snafu or septenary");
+            process::exit(1);
+        };
+
+        match convert(from, to, number) {
+            Ok(result) => println!("{}", result),
+            Err(e) => {
+                eprintln!("conversion failed: {}", e);
+                process::exit(1);
+            },
+        }
+
+        return;
+    }
+
     let path = Path::new("inputs/day25.txt");
     let reader = match File::open(path) {
         Err(e) => panic!("could not open input file at {}: {}", path.display(), e),
         Ok(file) => BufReader::new(file),
     };
 
-    let sum = reader
+    let answer: Snafu = reader
         .lines()
         .map(|l| l.unwrap())
-        .map(|l| from_snafu(&l))
+        .map(|l| l.parse::<Snafu>().unwrap_or_else(|e| panic!("{}", e)))
         .sum();
 
-    let answer = to_snafu(sum);
-    println!("The SNAFU number to supply to Bob's console is {}", answer);
+    if answer.signum() < 0 {
+        println!("The fuel requirements add up to a negative SNAFU number {}", answer);
+    } else {
+        println!("The SNAFU number to supply to Bob's console is {}", answer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // xorshift, enough to spread the checks over the whole i64 range
+    fn numbers(count: usize) -> impl Iterator<Item = i64> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+
+        (0..count).map(move |_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as i64 >> (state % 64)
+        })
+    }
+
+    fn decimal(value: &Snafu) -> String {
+        convert("snafu", "decimal", &value.to_string()).unwrap()
+    }
+
+    #[test]
+    fn puzzle_examples() {
+        for (value, snafu) in [(1, "1"), (3, "1="), (2022, "1=11-2"), (12345, "1-0---0"),
+                               (314159265, "1121-1110-1=0")] {
+            assert_eq!(Snafu::from(value).to_string(), snafu);
+            assert_eq!(snafu.parse::<Snafu>().unwrap(), Snafu::from(value));
+        }
+    }
+
+    #[test]
+    fn parse_format_round_trip() {
+        let mut numbers = vec![String::new()];
+        for _ in 0..6 {
+            numbers = numbers
+                .iter()
+                .flat_map(|n| SNAFU.alphabet.iter().map(move |&d| format!("{}{}", n, d as char)))
+                .filter(|n| !n.starts_with('0'))
+                .collect();
+
+            for n in &numbers {
+                assert_eq!(n.parse::<Snafu>().unwrap().to_string(), *n);
+            }
+        }
+
+        assert_eq!("0".parse::<Snafu>().unwrap().to_string(), "0");
+        assert_eq!("00-".parse::<Snafu>().unwrap().to_string(), "-");
+    }
+
+    #[test]
+    fn from_i64_matches_decimal() {
+        for value in numbers(1000).chain([0, 1, -1, i64::MIN, i64::MAX]) {
+            let snafu = Snafu::from(value);
+
+            assert_eq!(decimal(&snafu), value.to_string());
+            assert_eq!(convert("decimal", "snafu", &value.to_string()).unwrap(),
+                       snafu.to_string());
+        }
+    }
+
+    #[test]
+    fn add_then_sub() {
+        let values: Vec<_> = numbers(200).map(Snafu::from).collect();
+
+        for a in &values {
+            for b in values.iter().take(20) {
+                assert_eq!(a.clone() + b.clone() - b.clone(), *a);
+            }
+        }
+    }
+
+    #[test]
+    fn mul_and_neg_match_i128() {
+        let values: Vec<_> = numbers(100).chain([i64::MIN, i64::MAX]).collect();
+
+        for &a in &values {
+            assert_eq!(decimal(&-Snafu::from(a)), (-(a as i128)).to_string());
+
+            for &b in values.iter().take(20) {
+                let product = Snafu::from(a) * Snafu::from(b);
+                assert_eq!(decimal(&product), (a as i128 * b as i128).to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn ord_matches_i64() {
+        let values: Vec<_> = numbers(100).chain([0, 1, -1, 2, -2, i64::MIN]).collect();
+
+        for &a in &values {
+            for &b in &values {
+                assert_eq!(Snafu::from(a).cmp(&Snafu::from(b)), a.cmp(&b), "{} vs {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn longer_than_i64() {
+        let twos = "2".repeat(60);
+        let value: Snafu = twos.parse().unwrap();
+
+        assert_eq!(value.to_string(), twos);
+        assert!(value > Snafu::from(i64::MAX));
+        assert_eq!(value.clone() + Snafu::from(1) - Snafu::from(1), value);
+        assert_eq!((value.clone() * Snafu::from(-1)).signum(), -1);
+
+        let sum: Snafu = [value.clone(), value.clone(), -value.clone()].into_iter().sum();
+        assert_eq!(sum, value);
+    }
+
+    #[test]
+    fn convert_any_length() {
+        let twos = "2".repeat(60);
+        let value = "433680868994201773602981120347976684570312";
+
+        assert_eq!(convert("snafu", "decimal", &twos).unwrap(), value);
+        assert_eq!(convert("decimal", "snafu", value).unwrap(), twos);
+        assert_eq!(convert("decimal", "snafu", &format!("-{}", value)).unwrap(),
+                   "=".repeat(60));
+        assert_eq!(convert("snafu", "septenary", &twos).unwrap().len(), 50);
+        assert_eq!(convert("septenary", "snafu",
+                           &convert("snafu", "septenary", &twos).unwrap()).unwrap(), twos);
+        assert_eq!(convert("decimal", "ternary", "-007").unwrap(), "-1-");
+        assert_eq!(convert("ternary", "decimal", "000").unwrap(), "0");
+        assert!(convert("decimal", "snafu", "-").is_err());
+        assert!(convert("decimal", "snafu", "12a").is_err());
+    }
+
+    #[test]
+    fn empty_is_an_error() {
+        assert_eq!("".parse::<Snafu>(), Err(ParseBalancedError::Empty { base: "snafu" }));
+        assert!("1x".parse::<Snafu>().is_err());
+    }
 }